@@ -0,0 +1,213 @@
+use std::{
+    sync::{
+        Arc,
+        Mutex,
+    },
+    collections::{
+        BTreeMap,
+    },
+    ops::{
+        Bound,
+    },
+};
+
+use futures::{
+    channel::{
+        mpsc,
+    },
+};
+
+use async_trait::async_trait;
+
+use serde::{
+    Serialize,
+    Deserialize,
+};
+
+use alloc_pool::{
+    bytes::{
+        Bytes,
+    },
+};
+
+use crate::{
+    block,
+    Pid,
+    Flushed,
+    Deleted,
+    IterBlocks,
+    IterBlocksItem,
+    IterBlocksCursor,
+    FlushError,
+    ReadBlockError,
+    WriteBlockError,
+    IterBlocksError,
+    DeleteBlockError,
+};
+
+/// Block storage operations of `Pid`, so application code can be tested
+/// against [`FakeBlockStore`] without the actor stack.
+#[async_trait]
+pub trait BlockStore: Send {
+    /// Position in a blocks iteration, handed out with every item and
+    /// accepted back by [`BlockStore::iter_blocks_from`].
+    type Cursor: Send;
+
+    async fn flush(&mut self) -> Result<Flushed, FlushError>;
+
+    async fn write_block(&mut self, block_bytes: Bytes) -> Result<block::Id, WriteBlockError>;
+
+    async fn read_block(&mut self, block_id: block::Id) -> Result<Bytes, ReadBlockError>;
+
+    async fn delete_block(&mut self, block_id: block::Id) -> Result<Deleted, DeleteBlockError>;
+
+    async fn iter_blocks(&mut self) -> Result<IterBlocks<Self::Cursor>, IterBlocksError>;
+
+    async fn iter_blocks_from(&mut self, cursor: Self::Cursor) -> Result<IterBlocks<Self::Cursor>, IterBlocksError>;
+}
+
+#[async_trait]
+impl BlockStore for Pid {
+    type Cursor = IterBlocksCursor;
+
+    async fn flush(&mut self) -> Result<Flushed, FlushError> {
+        Pid::flush(self).await
+    }
+
+    async fn write_block(&mut self, block_bytes: Bytes) -> Result<block::Id, WriteBlockError> {
+        Pid::write_block(self, block_bytes).await
+    }
+
+    async fn read_block(&mut self, block_id: block::Id) -> Result<Bytes, ReadBlockError> {
+        Pid::read_block(self, block_id).await
+    }
+
+    async fn delete_block(&mut self, block_id: block::Id) -> Result<Deleted, DeleteBlockError> {
+        Pid::delete_block(self, block_id).await
+    }
+
+    async fn iter_blocks(&mut self) -> Result<IterBlocks, IterBlocksError> {
+        Pid::iter_blocks(self).await
+    }
+
+    async fn iter_blocks_from(&mut self, cursor: IterBlocksCursor) -> Result<IterBlocks, IterBlocksError> {
+        Pid::iter_blocks_from(self, cursor).await
+    }
+}
+
+/// In-memory [`BlockStore`] holding at most `capacity_bytes` of block data.
+///
+/// Block ids are handed out in increasing order and `iter_blocks` yields
+/// blocks in id order, which is the write order of a real wheel as long as
+/// it does not wrap around. Clones share the same storage.
+#[derive(Clone)]
+pub struct FakeBlockStore {
+    inner: Arc<Mutex<Inner>>,
+}
+
+/// Position in a [`FakeBlockStore`] blocks iteration, right after the block
+/// it came with.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FakeCursor {
+    block_id: block::Id,
+}
+
+struct Inner {
+    capacity_bytes: usize,
+    bytes_used: usize,
+    next_block_id: block::Id,
+    blocks: BTreeMap<block::Id, Bytes>,
+}
+
+impl FakeBlockStore {
+    pub fn new(capacity_bytes: usize) -> FakeBlockStore {
+        FakeBlockStore {
+            inner: Arc::new(Mutex::new(Inner {
+                capacity_bytes,
+                bytes_used: 0,
+                next_block_id: block::Id::init(),
+                blocks: BTreeMap::new(),
+            })),
+        }
+    }
+
+    pub fn blocks_count(&self) -> usize {
+        self.inner.lock().unwrap().blocks.len()
+    }
+
+    pub fn bytes_used(&self) -> usize {
+        self.inner.lock().unwrap().bytes_used
+    }
+
+    /// Snapshot of the blocks past `after`, or of all of them.
+    fn iter_blocks_after(&self, after: Bound<&block::Id>) -> IterBlocks<FakeCursor> {
+        let inner = self.inner.lock().unwrap();
+        let blocks: Vec<_> = inner.blocks.range((after, Bound::Unbounded)).collect();
+        // the channel is large enough to hold all the blocks along with the
+        // final marker
+        let (mut blocks_tx, blocks_rx) = mpsc::channel(blocks.len() + 1);
+        for (block_id, block_bytes) in blocks {
+            let item = IterBlocksItem::Block {
+                block_id: block_id.clone(),
+                block_bytes: block_bytes.clone(),
+                cursor: FakeCursor { block_id: block_id.clone(), },
+            };
+            blocks_tx.try_send(item)
+                .expect("fake iter blocks channel is sized for all blocks");
+        }
+        blocks_tx.try_send(IterBlocksItem::NoMoreBlocks)
+            .expect("fake iter blocks channel is sized for all blocks");
+        IterBlocks {
+            blocks_total_count: inner.blocks.len(),
+            blocks_total_size: inner.bytes_used,
+            blocks_rx,
+        }
+    }
+}
+
+#[async_trait]
+impl BlockStore for FakeBlockStore {
+    type Cursor = FakeCursor;
+
+    async fn flush(&mut self) -> Result<Flushed, FlushError> {
+        Ok(Flushed)
+    }
+
+    async fn write_block(&mut self, block_bytes: Bytes) -> Result<block::Id, WriteBlockError> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.bytes_used + block_bytes.len() > inner.capacity_bytes {
+            return Err(WriteBlockError::NoSpaceLeft);
+        }
+        let block_id = inner.next_block_id.clone();
+        inner.next_block_id = block_id.next();
+        inner.bytes_used += block_bytes.len();
+        inner.blocks.insert(block_id.clone(), block_bytes);
+        Ok(block_id)
+    }
+
+    async fn read_block(&mut self, block_id: block::Id) -> Result<Bytes, ReadBlockError> {
+        self.inner.lock().unwrap()
+            .blocks
+            .get(&block_id)
+            .cloned()
+            .ok_or(ReadBlockError::NotFound)
+    }
+
+    async fn delete_block(&mut self, block_id: block::Id) -> Result<Deleted, DeleteBlockError> {
+        let mut inner = self.inner.lock().unwrap();
+        let block_bytes = inner.blocks.remove(&block_id)
+            .ok_or(DeleteBlockError::NotFound)?;
+        inner.bytes_used -= block_bytes.len();
+        Ok(Deleted)
+    }
+
+    async fn iter_blocks(&mut self) -> Result<IterBlocks<FakeCursor>, IterBlocksError> {
+        Ok(self.iter_blocks_after(Bound::Unbounded))
+    }
+
+    /// Like [`crate::Pid::iter_blocks_from`], the totals of the reply
+    /// describe the whole store.
+    async fn iter_blocks_from(&mut self, cursor: FakeCursor) -> Result<IterBlocks<FakeCursor>, IterBlocksError> {
+        Ok(self.iter_blocks_after(Bound::Excluded(&cursor.block_id)))
+    }
+}
@@ -0,0 +1,325 @@
+use std::{
+    pin::{
+        pin,
+    },
+    sync::{
+        Arc,
+    },
+    task::{
+        Poll,
+        Context,
+    },
+    thread::{
+        self,
+        Thread,
+    },
+    time::{
+        Instant,
+        Duration,
+    },
+    future::{
+        Future,
+    },
+};
+
+use futures::{
+    task::{
+        self,
+        ArcWake,
+    },
+    StreamExt,
+};
+
+use alloc_pool::{
+    bytes::{
+        Bytes,
+    },
+};
+
+use crate::{
+    job,
+    block,
+    Pid,
+    Info,
+    Flushed,
+    Deleted,
+    IterBlocksStream,
+    InfoError,
+    FlushError,
+    ReadBlockError,
+    WriteBlockError,
+    IterBlocksError,
+    DeleteBlockError,
+    RequestError,
+};
+
+/// Synchronous facade over `Pid` for plain threads.
+///
+/// Every call parks the calling thread until the reply arrives, no async
+/// runtime is needed: the request is driven by the calling thread itself and
+/// the timeout of the wrapped `Pid` is enforced with a timed park instead of
+/// the tokio timer.
+///
+/// The blockwheel-fs jobs serving the request still run on the edeltraud
+/// pool, so a call parking one of its workers could starve them. Calls made
+/// from a worker of the pool fail right away with a `ThreadPoolWorker` error
+/// instead. A worker is recognized once it has run a job of this crate.
+#[derive(Clone)]
+pub struct BlockingPid {
+    pid: Pid,
+    timeout: Option<Duration>,
+}
+
+impl BlockingPid {
+    pub fn new(mut pid: Pid) -> BlockingPid {
+        let timeout = pid.timeout.take();
+        BlockingPid { pid, timeout, }
+    }
+
+    pub fn info(&mut self) -> Result<Info, InfoError> {
+        let deadline = self.deadline();
+        block_on_request(self.pid.info(), deadline)
+    }
+
+    pub fn flush(&mut self) -> Result<Flushed, FlushError> {
+        let deadline = self.deadline();
+        block_on_request(self.pid.flush(), deadline)
+    }
+
+    pub fn write_block(&mut self, block_bytes: Bytes) -> Result<block::Id, WriteBlockError> {
+        let deadline = self.deadline();
+        block_on_request(self.pid.write_block(block_bytes), deadline)
+    }
+
+    pub fn read_block(&mut self, block_id: block::Id) -> Result<Bytes, ReadBlockError> {
+        let deadline = self.deadline();
+        block_on_request(self.pid.read_block(block_id), deadline)
+    }
+
+    pub fn delete_block(&mut self, block_id: block::Id) -> Result<Deleted, DeleteBlockError> {
+        let deadline = self.deadline();
+        block_on_request(self.pid.delete_block(block_id), deadline)
+    }
+
+    pub fn iter_blocks(&mut self) -> Result<BlockingIterBlocks, IterBlocksError> {
+        let deadline = self.deadline();
+        let iter_blocks = block_on_request(self.pid.iter_blocks(), deadline)?;
+        Ok(BlockingIterBlocks {
+            blocks_total_count: iter_blocks.blocks_total_count,
+            blocks_total_size: iter_blocks.blocks_total_size,
+            blocks: iter_blocks.into_stream(),
+            timeout: self.timeout,
+        })
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| Instant::now() + timeout)
+    }
+}
+
+/// Blocking iterator over the wheel blocks, see [`IterBlocksStream`].
+///
+/// The timeout of the `BlockingPid` applies to each item separately: an item
+/// not received in time is reported as `IterBlocksError::Timeout`, and the
+/// iteration may be continued.
+pub struct BlockingIterBlocks {
+    pub blocks_total_count: usize,
+    pub blocks_total_size: usize,
+    blocks: IterBlocksStream,
+    timeout: Option<Duration>,
+}
+
+impl Iterator for BlockingIterBlocks {
+    type Item = Result<(block::Id, Bytes), IterBlocksError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if job::is_thread_pool_worker() {
+            return Some(Err(IterBlocksError::ThreadPoolWorker));
+        }
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        block_on_until(self.blocks.next(), deadline)
+            .unwrap_or(Some(Err(IterBlocksError::Timeout)))
+    }
+}
+
+/// Runs a request on the calling thread unless it is a thread pool worker,
+/// failing with a `Timeout` error if `deadline` passes first.
+fn block_on_request<F, T, E>(future: F, deadline: Option<Instant>) -> Result<T, E>
+where F: Future<Output = Result<T, E>>,
+      E: RequestError,
+{
+    if job::is_thread_pool_worker() {
+        return Err(E::thread_pool_worker());
+    }
+    block_on_until(future, deadline)
+        .unwrap_or(Err(E::timeout()))
+}
+
+/// Polls `future` on the calling thread, parking it in between. Returns
+/// `None` if `deadline` passes first.
+fn block_on_until<F>(future: F, deadline: Option<Instant>) -> Option<F::Output>
+where F: Future,
+{
+    let mut future = pin!(future);
+    let waker = task::waker(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return Some(output);
+        }
+        match deadline {
+            None =>
+                thread::park(),
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return None;
+                }
+                thread::park_timeout(deadline - now);
+            },
+        }
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl ArcWake for ThreadWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.0.unpark();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            mpsc,
+        },
+        time::{
+            Duration,
+        },
+    };
+
+    use alloc_pool::{
+        bytes::{
+            BytesPool,
+        },
+    };
+
+    use crate::{
+        echo_policy::{
+            EchoPolicy,
+        },
+        job,
+        codec,
+        GenServer,
+        InfoError,
+        IterBlocksError,
+    };
+
+    use super::{
+        BlockingPid,
+        BlockingIterBlocks,
+    };
+
+    /// Pool job type of an application which runs its own jobs on the pool
+    /// serving the gen server.
+    enum AppJob {
+        Blockwheel(job::Job),
+        Call(Box<dyn FnOnce() + Send>),
+    }
+
+    impl From<blockwheel_fs::job::SklaveJob<EchoPolicy>> for AppJob {
+        fn from(job: blockwheel_fs::job::SklaveJob<EchoPolicy>) -> Self {
+            Self::Blockwheel(job.into())
+        }
+    }
+
+    impl From<blockwheel_fs::job::BlockPrepareWriteJob<EchoPolicy>> for AppJob {
+        fn from(job: blockwheel_fs::job::BlockPrepareWriteJob<EchoPolicy>) -> Self {
+            Self::Blockwheel(job.into())
+        }
+    }
+
+    impl From<blockwheel_fs::job::BlockPrepareDeleteJob<EchoPolicy>> for AppJob {
+        fn from(job: blockwheel_fs::job::BlockPrepareDeleteJob<EchoPolicy>) -> Self {
+            Self::Blockwheel(job.into())
+        }
+    }
+
+    impl From<blockwheel_fs::job::BlockProcessReadJob<EchoPolicy>> for AppJob {
+        fn from(job: blockwheel_fs::job::BlockProcessReadJob<EchoPolicy>) -> Self {
+            Self::Blockwheel(job.into())
+        }
+    }
+
+    impl From<codec::CodecJob> for AppJob {
+        fn from(job: codec::CodecJob) -> Self {
+            Self::Blockwheel(job.into())
+        }
+    }
+
+    struct AppJobUnit(edeltraud::JobUnit<AppJob, AppJob>);
+
+    impl From<edeltraud::JobUnit<AppJob, AppJob>> for AppJobUnit {
+        fn from(job_unit: edeltraud::JobUnit<AppJob, AppJob>) -> Self {
+            Self(job_unit)
+        }
+    }
+
+    impl edeltraud::Job for AppJobUnit {
+        fn run(self) {
+            let edeltraud::JobUnit { handle, job, } = self.0;
+            match job {
+                AppJob::Blockwheel(blockwheel_job) =>
+                    edeltraud::Job::run(job::JobUnit::from(edeltraud::JobUnit { handle, job: blockwheel_job, })),
+                AppJob::Call(call) =>
+                    call(),
+            }
+        }
+    }
+
+    #[test]
+    fn call_from_thread_pool_job_fails_fast() {
+        let thread_pool = edeltraud::Builder::new()
+            .worker_threads(1)
+            .build::<_, AppJobUnit>()
+            .unwrap();
+        let handle = thread_pool.handle();
+
+        // the only worker runs a job of this crate first
+        let blocks_pool = BytesPool::new();
+        futures::executor::block_on(
+            codec::encode(&handle, &blocks_pool, codec::Settings::default(), blocks_pool.lend().freeze()),
+        ).unwrap();
+
+        // the server is never run and the pid has no timeout, so a call
+        // parking the worker would never return
+        let (result_tx, result_rx) = mpsc::channel();
+        edeltraud::job(&handle, AppJob::Call(Box::new(move || {
+            let gen_server = GenServer::new();
+            let mut blocking_pid = BlockingPid::new(gen_server.pid());
+            result_tx.send(blocking_pid.info()).unwrap();
+            drop(gen_server);
+        }))).unwrap();
+        let result = result_rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(matches!(result, Err(InfoError::ThreadPoolWorker)));
+    }
+
+    #[test]
+    fn iterator_item_times_out() {
+        let (_blocks_tx, blocks_rx) = futures::channel::mpsc::channel(0);
+        let iter_blocks = crate::IterBlocks {
+            blocks_total_count: 1,
+            blocks_total_size: 0,
+            blocks_rx,
+        };
+        let mut blocks = BlockingIterBlocks {
+            blocks_total_count: iter_blocks.blocks_total_count,
+            blocks_total_size: iter_blocks.blocks_total_size,
+            blocks: iter_blocks.into_stream(),
+            timeout: Some(Duration::from_millis(100)),
+        };
+        assert!(matches!(blocks.next(), Some(Err(IterBlocksError::Timeout))));
+    }
+}
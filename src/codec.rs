@@ -0,0 +1,564 @@
+//! Optional per-block compression, encryption and checksums.
+//!
+//! A checksummed block starts with [`CHECKSUM_MAGIC`] followed by the crc32c
+//! of the rest of the block. An encrypted block starts with
+//! [`ENCRYPTION_MAGIC`] followed by the key id and the nonce. A compressed
+//! block starts with [`MAGIC`] followed by a codec byte. When several are
+//! enabled they are nested in this order, the checksum frame outermost.
+//!
+//! The encryption tag authenticates the magic and the key id only. The block
+//! id is assigned by blockwheel-fs after the block is sealed, so it cannot be
+//! bound: a sealed block copied under another id still opens there.
+//!
+//! Blocks without a header are stored as is, so blocks written before these
+//! options were turned on keep reading correctly. Raw blocks which happen to
+//! start with one of the magics are wrapped with the `STORED` codec on write.
+//!
+//! Framing is recognized by the magic prefix alone. A block written as is by
+//! plain blockwheel-fs or by a version of this crate without the codec,
+//! whose payload happens to start with one of the magics, is therefore taken
+//! for a framed block: it fails to decode and is reported as corrupted or
+//! undecodable instead of being returned. Such blocks have to be read back
+//! with plain blockwheel-fs and written again through this crate.
+
+#[cfg(feature = "encryption")]
+use std::sync::Arc;
+
+use futures::{
+    channel::{
+        oneshot,
+    },
+};
+
+use alloc_pool::{
+    bytes::{
+        Bytes,
+        BytesPool,
+    },
+};
+
+const MAGIC: &[u8; 4] = b"bwcz";
+const HEADER_SIZE: usize = MAGIC.len() + 1;
+
+const CHECKSUM_MAGIC: &[u8; 4] = b"bwck";
+const CHECKSUM_SIZE: usize = 4;
+
+const ENCRYPTION_MAGIC: &[u8; 4] = b"bwen";
+const ENCRYPTION_AAD_SIZE: usize = ENCRYPTION_MAGIC.len() + 4;
+
+const CODEC_STORED: u8 = 0;
+#[cfg(feature = "zstd")]
+const CODEC_ZSTD: u8 = 1;
+#[cfg(feature = "lz4")]
+const CODEC_LZ4: u8 = 2;
+
+/// Compression applied to newly written blocks, see
+/// [`crate::GenServer::with_compression`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    #[cfg(feature = "zstd")]
+    Zstd { level: i32, },
+    #[cfg(feature = "lz4")]
+    Lz4,
+}
+
+/// Block encoding configured through the `GenServer` builder methods.
+#[derive(Clone, Default, Debug)]
+pub struct Settings {
+    pub compression: Compression,
+    pub checksums: bool,
+    #[cfg(feature = "encryption")]
+    pub encryption: Option<Arc<crate::encryption::EncryptionKeys>>,
+}
+
+impl Settings {
+    #[cfg(feature = "encryption")]
+    fn encrypts(&self) -> bool {
+        self.encryption.is_some()
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    fn encrypts(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Spawn(edeltraud::SpawnError),
+    JobIsLost,
+    UnknownCodec(u8),
+    Decompress(std::io::Error),
+    ChecksumMismatch,
+    UnknownKey(u32),
+    DecryptionFailed,
+}
+
+pub struct CodecJob {
+    task: Task,
+    blocks_pool: BytesPool,
+    reply_tx: oneshot::Sender<Result<Bytes, Error>>,
+}
+
+enum Task {
+    Encode { settings: Settings, block_bytes: Bytes, },
+    Decode { settings: Settings, block_bytes: Bytes, },
+}
+
+/// Whether `block_bytes` has to go through [`encode`] before being written.
+pub fn needs_encoding(settings: &Settings, block_bytes: &[u8]) -> bool {
+    settings.compression != Compression::None
+        || settings.checksums
+        || settings.encrypts()
+        || has_magic(block_bytes)
+}
+
+/// Whether `block_bytes` has to go through [`decode`] after being read.
+pub fn needs_decoding(block_bytes: &[u8]) -> bool {
+    has_magic(block_bytes)
+}
+
+/// Whether `block_bytes` is wrapped with a checksum frame.
+pub fn has_checksum(block_bytes: &[u8]) -> bool {
+    block_bytes.starts_with(CHECKSUM_MAGIC)
+}
+
+fn has_magic(block_bytes: &[u8]) -> bool {
+    block_bytes.starts_with(MAGIC)
+        || block_bytes.starts_with(CHECKSUM_MAGIC)
+        || block_bytes.starts_with(ENCRYPTION_MAGIC)
+}
+
+pub async fn encode<J>(
+    thread_pool: &edeltraud::Handle<J>,
+    blocks_pool: &BytesPool,
+    settings: Settings,
+    block_bytes: Bytes,
+)
+    -> Result<Bytes, Error>
+where J: From<CodecJob>,
+{
+    run(thread_pool, blocks_pool, Task::Encode { settings, block_bytes, }).await
+}
+
+pub async fn decode<J>(
+    thread_pool: &edeltraud::Handle<J>,
+    blocks_pool: &BytesPool,
+    settings: Settings,
+    block_bytes: Bytes,
+)
+    -> Result<Bytes, Error>
+where J: From<CodecJob>,
+{
+    run(thread_pool, blocks_pool, Task::Decode { settings, block_bytes, }).await
+}
+
+async fn run<J>(thread_pool: &edeltraud::Handle<J>, blocks_pool: &BytesPool, task: Task) -> Result<Bytes, Error>
+where J: From<CodecJob>,
+{
+    let (reply_tx, reply_rx) = oneshot::channel();
+    let codec_job = CodecJob {
+        task,
+        blocks_pool: blocks_pool.clone(),
+        reply_tx,
+    };
+    edeltraud::job(thread_pool, codec_job)
+        .map_err(Error::Spawn)?;
+    reply_rx.await
+        .map_err(|oneshot::Canceled| Error::JobIsLost)?
+}
+
+pub fn job(codec_job: CodecJob) {
+    let CodecJob { task, blocks_pool, reply_tx, } = codec_job;
+    let result = match task {
+        Task::Encode { settings, block_bytes, } => {
+            let block_bytes = run_compress(&blocks_pool, settings.compression, block_bytes);
+            #[cfg(feature = "encryption")]
+            let block_bytes = match &settings.encryption {
+                Some(keys) =>
+                    run_encrypt(&blocks_pool, keys, &block_bytes),
+                None =>
+                    block_bytes,
+            };
+            if settings.checksums {
+                Ok(run_checksum(&blocks_pool, &block_bytes))
+            } else {
+                Ok(block_bytes)
+            }
+        },
+        Task::Decode { settings, block_bytes, } =>
+            run_decode(&blocks_pool, &settings, &block_bytes),
+    };
+    if let Err(_send_error) = reply_tx.send(result) {
+        log::debug!("gen server is gone during codec job");
+    }
+}
+
+fn run_compress(blocks_pool: &BytesPool, compression: Compression, block_bytes: Bytes) -> Bytes {
+    let mut encoded = blocks_pool.lend();
+    encoded.extend_from_slice(MAGIC);
+    match compression {
+        Compression::None =>
+            (),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd { level, } => {
+            encoded.push(CODEC_ZSTD);
+            if let Err(error) = zstd::stream::copy_encode(&block_bytes[..], &mut *encoded, level) {
+                log::warn!("zstd compression failed, storing block uncompressed: {:?}", error);
+                encoded.truncate(MAGIC.len());
+            }
+        },
+        #[cfg(feature = "lz4")]
+        Compression::Lz4 => {
+            encoded.push(CODEC_LZ4);
+            encoded.extend_from_slice(&lz4_flex::compress_prepend_size(&block_bytes));
+        },
+    }
+
+    if encoded.len() > MAGIC.len() && encoded.len() < block_bytes.len() {
+        return encoded.freeze();
+    }
+    if !has_magic(&block_bytes) {
+        // compression did not pay off
+        return block_bytes;
+    }
+    encoded.truncate(MAGIC.len());
+    encoded.push(CODEC_STORED);
+    encoded.extend_from_slice(&block_bytes);
+    encoded.freeze()
+}
+
+fn run_checksum(blocks_pool: &BytesPool, block_bytes: &[u8]) -> Bytes {
+    let mut encoded = blocks_pool.lend();
+    encoded.reserve(CHECKSUM_MAGIC.len() + CHECKSUM_SIZE + block_bytes.len());
+    encoded.extend_from_slice(CHECKSUM_MAGIC);
+    encoded.extend_from_slice(&crc32c::crc32c(block_bytes).to_le_bytes());
+    encoded.extend_from_slice(block_bytes);
+    encoded.freeze()
+}
+
+#[cfg(feature = "encryption")]
+fn run_encrypt(blocks_pool: &BytesPool, keys: &crate::encryption::EncryptionKeys, block_bytes: &[u8]) -> Bytes {
+    let mut encoded = blocks_pool.lend();
+    encoded.extend_from_slice(ENCRYPTION_MAGIC);
+    encoded.extend_from_slice(&keys.active_key_id().to_le_bytes());
+    let (nonce, ciphertext) = crate::encryption::seal(keys, &encoded[..], block_bytes);
+    encoded.extend_from_slice(&nonce);
+    encoded.extend_from_slice(&ciphertext);
+    encoded.freeze()
+}
+
+fn sealed_key_id(aad: &[u8]) -> u32 {
+    u32::from_le_bytes(aad[ENCRYPTION_MAGIC.len() .. ENCRYPTION_AAD_SIZE].try_into().unwrap())
+}
+
+#[cfg(feature = "encryption")]
+fn run_decrypt(settings: &Settings, sealed: &[u8]) -> Result<Vec<u8>, Error> {
+    use crate::encryption::NONCE_SIZE;
+
+    if sealed.len() < ENCRYPTION_AAD_SIZE + NONCE_SIZE {
+        return Err(Error::DecryptionFailed);
+    }
+    let (aad, rest) = sealed.split_at(ENCRYPTION_AAD_SIZE);
+    let key_id = sealed_key_id(aad);
+    let (nonce, ciphertext) = rest.split_at(NONCE_SIZE);
+    let keys = settings.encryption.as_ref()
+        .ok_or(Error::UnknownKey(key_id))?;
+    crate::encryption::open(keys, key_id, aad, nonce.try_into().unwrap(), ciphertext)
+        .map_err(|error| match error {
+            crate::encryption::OpenError::UnknownKey(key_id) =>
+                Error::UnknownKey(key_id),
+            crate::encryption::OpenError::TagMismatch =>
+                Error::DecryptionFailed,
+        })
+}
+
+#[cfg(not(feature = "encryption"))]
+fn run_decrypt(_settings: &Settings, sealed: &[u8]) -> Result<Vec<u8>, Error> {
+    if sealed.len() < ENCRYPTION_AAD_SIZE {
+        return Err(Error::DecryptionFailed);
+    }
+    Err(Error::UnknownKey(sealed_key_id(sealed)))
+}
+
+fn run_decode(blocks_pool: &BytesPool, settings: &Settings, block_bytes: &[u8]) -> Result<Bytes, Error> {
+    let mut payload = block_bytes;
+    if let Some(checksummed) = payload.strip_prefix(&CHECKSUM_MAGIC[..]) {
+        if checksummed.len() < CHECKSUM_SIZE {
+            return Err(Error::ChecksumMismatch);
+        }
+        let (checksum_bytes, data) = checksummed.split_at(CHECKSUM_SIZE);
+        let checksum = u32::from_le_bytes(checksum_bytes.try_into().unwrap());
+        if crc32c::crc32c(data) != checksum {
+            return Err(Error::ChecksumMismatch);
+        }
+        payload = data;
+    }
+
+    let decrypted;
+    if payload.starts_with(ENCRYPTION_MAGIC) {
+        decrypted = run_decrypt(settings, payload)?;
+        payload = &decrypted[..];
+    }
+
+    let mut decoded = blocks_pool.lend();
+    if !payload.starts_with(MAGIC) || payload.len() < HEADER_SIZE {
+        decoded.extend_from_slice(payload);
+        return Ok(decoded.freeze());
+    }
+    let codec = payload[MAGIC.len()];
+    let compressed = &payload[HEADER_SIZE ..];
+    match codec {
+        CODEC_STORED =>
+            decoded.extend_from_slice(compressed),
+        #[cfg(feature = "zstd")]
+        CODEC_ZSTD =>
+            zstd::stream::copy_decode(compressed, &mut *decoded)
+                .map_err(Error::Decompress)?,
+        #[cfg(feature = "lz4")]
+        CODEC_LZ4 => {
+            let bytes = lz4_flex::decompress_size_prepended(compressed)
+                .map_err(|error| Error::Decompress(std::io::Error::new(std::io::ErrorKind::InvalidData, error)))?;
+            decoded.extend_from_slice(&bytes);
+        },
+        unknown =>
+            return Err(Error::UnknownCodec(unknown)),
+    }
+    Ok(decoded.freeze())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{
+        channel::{
+            oneshot,
+        },
+    };
+
+    use alloc_pool::{
+        bytes::{
+            Bytes,
+            BytesPool,
+        },
+    };
+
+    use super::{
+        job,
+        needs_decoding,
+        has_checksum,
+        Task,
+        Error,
+        CodecJob,
+        Settings,
+        Compression,
+        MAGIC,
+        CHECKSUM_MAGIC,
+    };
+
+    fn run_job(task: Task) -> Result<Bytes, Error> {
+        let (reply_tx, mut reply_rx) = oneshot::channel();
+        job(CodecJob { task, blocks_pool: BytesPool::new(), reply_tx, });
+        reply_rx.try_recv().unwrap().unwrap()
+    }
+
+    fn encode(settings: &Settings, block_bytes: &[u8]) -> Bytes {
+        run_job(Task::Encode { settings: settings.clone(), block_bytes: bytes(block_bytes), }).unwrap()
+    }
+
+    fn decode(block_bytes: &[u8]) -> Result<Bytes, Error> {
+        decode_with(&Settings::default(), block_bytes)
+    }
+
+    fn decode_with(settings: &Settings, block_bytes: &[u8]) -> Result<Bytes, Error> {
+        run_job(Task::Decode { settings: settings.clone(), block_bytes: bytes(block_bytes), })
+    }
+
+    fn bytes(block_bytes: &[u8]) -> Bytes {
+        let mut bytes = BytesPool::new().lend();
+        bytes.extend_from_slice(block_bytes);
+        bytes.freeze()
+    }
+
+    fn settings(compression: Compression, checksums: bool) -> Settings {
+        Settings {
+            compression,
+            checksums,
+            #[cfg(feature = "encryption")]
+            encryption: None,
+        }
+    }
+
+    fn compressible_block() -> Vec<u8> {
+        b"blockwheel ".iter().cycle().take(64 * 1024).cloned().collect()
+    }
+
+    fn compressions() -> Vec<Compression> {
+        vec![
+            Compression::None,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level: 3, },
+            #[cfg(feature = "lz4")]
+            Compression::Lz4,
+        ]
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut block_with_magic = MAGIC.to_vec();
+        block_with_magic.extend_from_slice(&compressible_block());
+        for compression in compressions() {
+            for checksums in [false, true] {
+                let settings = settings(compression, checksums);
+                for block_bytes in [compressible_block(), b"tiny".to_vec(), block_with_magic.clone(), Vec::new()] {
+                    let encoded = encode(&settings, &block_bytes);
+                    assert_eq!(has_checksum(&encoded), checksums);
+                    let decoded = if needs_decoding(&encoded) {
+                        decode(&encoded).unwrap()
+                    } else {
+                        encoded
+                    };
+                    assert_eq!(&decoded[..], &block_bytes[..], "{:?}, checksums: {}", compression, checksums);
+                }
+            }
+        }
+    }
+
+    #[cfg(any(feature = "zstd", feature = "lz4"))]
+    #[test]
+    fn compression_shrinks_block() {
+        for compression in compressions().into_iter().filter(|compression| *compression != Compression::None) {
+            let settings = settings(compression, false);
+            let encoded = encode(&settings, &compressible_block());
+            assert!(encoded.starts_with(MAGIC));
+            assert!(encoded.len() < compressible_block().len() / 4, "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let settings = settings(Compression::None, true);
+        let encoded = encode(&settings, &compressible_block());
+        for offset in [CHECKSUM_MAGIC.len(), encoded.len() / 2, encoded.len() - 1] {
+            let mut corrupted = encoded.to_vec();
+            corrupted[offset] ^= 0x01;
+            assert!(matches!(decode(&corrupted), Err(Error::ChecksumMismatch)), "flipped byte at offset {}", offset);
+        }
+        assert!(matches!(decode(&encoded[.. CHECKSUM_MAGIC.len() + 2]), Err(Error::ChecksumMismatch)));
+    }
+
+    #[test]
+    fn legacy_raw_block_reads_as_is() {
+        // a block stored as is before the codec existed
+        let block_bytes = compressible_block();
+        assert!(!needs_decoding(&block_bytes));
+        assert_eq!(&decode(&block_bytes).unwrap()[..], &block_bytes[..]);
+    }
+
+    #[test]
+    fn legacy_raw_block_with_magic_is_taken_for_framed() {
+        // the documented collision: a block stored as is before the codec
+        // existed, which starts with a magic, is not returned as is
+        let mut block_bytes = CHECKSUM_MAGIC.to_vec();
+        block_bytes.extend_from_slice(b"legacy payload");
+        assert!(needs_decoding(&block_bytes));
+        assert!(matches!(decode(&block_bytes), Err(Error::ChecksumMismatch)));
+    }
+
+    #[test]
+    fn unknown_codec() {
+        let mut block_bytes = MAGIC.to_vec();
+        block_bytes.push(0xff);
+        block_bytes.extend_from_slice(b"payload");
+        assert!(matches!(decode(&block_bytes), Err(Error::UnknownCodec(0xff))));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn corrupted_zstd_frame() {
+        let settings = settings(Compression::Zstd { level: 3, }, false);
+        let mut encoded = encode(&settings, &compressible_block()).to_vec();
+        encoded.truncate(encoded.len() / 2);
+        assert!(matches!(decode(&encoded), Err(Error::Decompress(..))));
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn corrupted_lz4_frame() {
+        let settings = settings(Compression::Lz4, false);
+        let mut encoded = encode(&settings, &compressible_block()).to_vec();
+        encoded.truncate(encoded.len() / 2);
+        assert!(matches!(decode(&encoded), Err(Error::Decompress(..))));
+    }
+
+    #[cfg(feature = "encryption")]
+    fn encrypted(keys: crate::encryption::EncryptionKeys, checksums: bool) -> Settings {
+        Settings {
+            compression: Compression::None,
+            checksums,
+            encryption: Some(std::sync::Arc::new(keys)),
+        }
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypted_round_trip() {
+        use crate::encryption::EncryptionKeys;
+
+        for compression in compressions() {
+            for checksums in [false, true] {
+                let settings = Settings {
+                    compression,
+                    ..encrypted(EncryptionKeys::new(1, [7; 32]), checksums)
+                };
+                let block_bytes = compressible_block();
+                let encoded = encode(&settings, &block_bytes);
+                assert!(needs_decoding(&encoded));
+                assert!(!encoded.windows(11).any(|window| window == b"blockwheel "));
+                assert_eq!(&decode_with(&settings, &encoded).unwrap()[..], &block_bytes[..]);
+            }
+        }
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn old_key_opens_after_rotation() {
+        use crate::encryption::EncryptionKeys;
+
+        let block_bytes = compressible_block();
+        let encoded = encode(&encrypted(EncryptionKeys::new(1, [1; 32]), true), &block_bytes);
+        let rotated = encrypted(EncryptionKeys::new(2, [2; 32]).with_decryption_key(1, [1; 32]), true);
+        assert_eq!(&decode_with(&rotated, &encoded).unwrap()[..], &block_bytes[..]);
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn unknown_key_id() {
+        use crate::encryption::EncryptionKeys;
+
+        let encoded = encode(&encrypted(EncryptionKeys::new(1, [1; 32]), true), b"secret");
+        let other = encrypted(EncryptionKeys::new(2, [2; 32]), true);
+        assert!(matches!(decode_with(&other, &encoded), Err(Error::UnknownKey(1))));
+        assert!(matches!(decode(&encoded), Err(Error::UnknownKey(1))));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn tampered_block_fails_to_decrypt() {
+        use crate::encryption::EncryptionKeys;
+
+        // without the checksum frame, which would catch the damage first
+        let settings = encrypted(EncryptionKeys::new(1, [1; 32]), false);
+        let encoded = encode(&settings, &compressible_block());
+        // a byte of the ciphertext, then a byte of the trailing tag
+        for offset in [encoded.len() / 2, encoded.len() - 1] {
+            let mut tampered = encoded.to_vec();
+            tampered[offset] ^= 0x01;
+            assert!(matches!(decode_with(&settings, &tampered), Err(Error::DecryptionFailed)));
+        }
+        // the wrong key under a known key id
+        let forged = encrypted(EncryptionKeys::new(1, [9; 32]), false);
+        assert!(matches!(decode_with(&forged, &encoded), Err(Error::DecryptionFailed)));
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    fmt,
+    collections::{
+        HashMap,
+    },
+};
+
+use chacha20poly1305::{
+    aead::{
+        Aead,
+        AeadCore,
+        KeyInit,
+        OsRng,
+        Payload,
+    },
+    ChaCha20Poly1305,
+    Nonce,
+    Key,
+};
+
+pub const NONCE_SIZE: usize = 12;
+
+/// Keys for the ChaCha20-Poly1305 block encryption, see
+/// [`crate::GenServer::with_encryption`].
+///
+/// New blocks are sealed with the active key, its id is stored in the
+/// block header so blocks sealed with older keys can still be opened as
+/// long as those keys are kept around.
+#[derive(Clone)]
+pub struct EncryptionKeys {
+    active_key_id: u32,
+    keys: HashMap<u32, Key>,
+}
+
+impl fmt::Debug for EncryptionKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptionKeys")
+            .field("active_key_id", &self.active_key_id)
+            .field("key_ids", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl EncryptionKeys {
+    pub fn new(key_id: u32, key: [u8; 32]) -> EncryptionKeys {
+        let mut keys = HashMap::new();
+        keys.insert(key_id, Key::from(key));
+        EncryptionKeys { active_key_id: key_id, keys, }
+    }
+
+    /// Adds a retired key which is only used to open blocks sealed before
+    /// the rotation.
+    pub fn with_decryption_key(mut self, key_id: u32, key: [u8; 32]) -> EncryptionKeys {
+        self.keys.entry(key_id).or_insert_with(|| Key::from(key));
+        self
+    }
+
+    pub fn active_key_id(&self) -> u32 {
+        self.active_key_id
+    }
+}
+
+pub enum OpenError {
+    UnknownKey(u32),
+    TagMismatch,
+}
+
+/// Seals `plaintext` with the active key, returning the nonce and the
+/// ciphertext with the tag appended. `aad` must contain the key id.
+pub fn seal(keys: &EncryptionKeys, aad: &[u8], plaintext: &[u8]) -> ([u8; NONCE_SIZE], Vec<u8>) {
+    let cipher = ChaCha20Poly1305::new(&keys.keys[&keys.active_key_id]);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher.encrypt(&nonce, Payload { msg: plaintext, aad, })
+        .expect("chacha20poly1305 encryption into memory buffer failed");
+    (nonce.into(), ciphertext)
+}
+
+pub fn open(
+    keys: &EncryptionKeys,
+    key_id: u32,
+    aad: &[u8],
+    nonce: &[u8; NONCE_SIZE],
+    ciphertext: &[u8],
+)
+    -> Result<Vec<u8>, OpenError>
+{
+    let key = keys.keys.get(&key_id)
+        .ok_or(OpenError::UnknownKey(key_id))?;
+    ChaCha20Poly1305::new(key)
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad, })
+        .map_err(|_aead_error| OpenError::TagMismatch)
+}
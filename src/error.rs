@@ -0,0 +1,649 @@
+use std::{
+    fmt,
+};
+
+use crate::{
+    block,
+    proto,
+    gen_server,
+};
+
+/// Umbrella error every request specific error converts into.
+#[derive(Debug)]
+pub enum Error {
+    GenServer(ero::NoProcError),
+    Info(InfoError),
+    Flush(FlushError),
+    WriteBlock(WriteBlockError),
+    ReadBlock(ReadBlockError),
+    DeleteBlock(DeleteBlockError),
+    IterBlocks(IterBlocksError),
+    Resync(ResyncError),
+    Put(PutError),
+    Sharding(ShardingError),
+    WriteObject(WriteObjectError),
+    ReadObject(ReadObjectError),
+    DeleteObject(DeleteObjectError),
+    Stats(StatsError),
+    Shutdown(ShutdownError),
+}
+
+#[derive(Debug)]
+pub enum InfoError {
+    GenServer(ero::NoProcError),
+    Timeout,
+    /// A [`crate::BlockingPid`] call was made from a worker of the thread
+    /// pool, where waiting for the reply could starve the jobs serving it.
+    ThreadPoolWorker,
+}
+
+#[derive(Debug)]
+pub enum FlushError {
+    GenServer(ero::NoProcError),
+    Timeout,
+    /// A [`crate::BlockingPid`] call was made from a worker of the thread
+    /// pool, where waiting for the reply could starve the jobs serving it.
+    ThreadPoolWorker,
+}
+
+#[derive(Debug)]
+pub enum WriteBlockError {
+    GenServer(ero::NoProcError),
+    NoSpaceLeft,
+    Timeout,
+    /// A [`crate::BlockingPid`] call was made from a worker of the thread
+    /// pool, where waiting for the reply could starve the jobs serving it.
+    ThreadPoolWorker,
+    /// The request has been lost in flight, so the block may or may not have
+    /// been written.
+    OutcomeUnknown,
+}
+
+#[derive(Debug)]
+pub enum ReadBlockError {
+    GenServer(ero::NoProcError),
+    NotFound,
+    Timeout,
+    /// A [`crate::BlockingPid`] call was made from a worker of the thread
+    /// pool, where waiting for the reply could starve the jobs serving it.
+    ThreadPoolWorker,
+    /// The block checksum does not match its contents.
+    Corrupted,
+    /// The block could not be decrypted: either its key is unknown or the
+    /// authentication tag check failed.
+    DecryptionFailed,
+    /// The block frame names a codec which is not compiled in or the block
+    /// fails to decompress.
+    Undecodable,
+    /// The decoding job could not run on the thread pool.
+    DecodingFailed,
+}
+
+#[derive(Debug)]
+pub enum DeleteBlockError {
+    GenServer(ero::NoProcError),
+    NotFound,
+    Timeout,
+    /// A [`crate::BlockingPid`] call was made from a worker of the thread
+    /// pool, where waiting for the reply could starve the jobs serving it.
+    ThreadPoolWorker,
+}
+
+#[derive(Debug)]
+pub enum IterBlocksError {
+    GenServer(ero::NoProcError),
+    Timeout,
+    /// A [`crate::BlockingPid`] call was made from a worker of the thread
+    /// pool, where waiting for the reply could starve the jobs serving it.
+    ThreadPoolWorker,
+    Interrupted,
+    /// The block could not be decoded, the stream goes on past it.
+    Corrupted { block_id: block::Id, },
+}
+
+#[derive(Debug)]
+pub enum StatsError {
+    GenServer(ero::NoProcError),
+    Timeout,
+}
+
+#[derive(Debug)]
+pub enum ShutdownError {
+    GenServer(ero::NoProcError),
+    /// The request could not be submitted in time, the server is left
+    /// running.
+    Timeout,
+}
+
+#[derive(Debug)]
+pub enum WriteObjectError {
+    /// Objects cannot be split into empty chunks.
+    ZeroChunkSize,
+    WriteBlock(WriteBlockError),
+}
+
+#[derive(Debug)]
+pub enum ReadObjectError {
+    ReadBlock(ReadBlockError),
+    /// The block is not an object manifest, or the manifest does not match
+    /// the chunks it lists.
+    InvalidManifest,
+}
+
+#[derive(Debug)]
+pub enum DeleteObjectError {
+    ReadManifest(ReadObjectError),
+    DeleteBlock(DeleteBlockError),
+}
+
+#[derive(Debug)]
+pub enum ResyncError {
+    IterBlocks(IterBlocksError),
+    ReadBlock(ReadBlockError),
+    WriteBlock(WriteBlockError),
+}
+
+#[derive(Debug)]
+pub enum PutError {
+    /// The key length does not fit into the record header.
+    KeyTooLong,
+    WriteBlock(WriteBlockError),
+}
+
+#[derive(Debug)]
+pub enum ShardingError {
+    /// A sharded frontend needs at least one shard.
+    NoShards,
+}
+
+const GEN_SERVER_IS_GONE: &str = "blockwheel_fs gen server is gone";
+const TIMEOUT: &str = "request timed out";
+const THREAD_POOL_WORKER: &str = "blocking request from a thread pool worker";
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::GenServer(ero::NoProcError) =>
+                write!(f, "{}", GEN_SERVER_IS_GONE),
+            Error::Info(..) =>
+                write!(f, "info request failed"),
+            Error::Flush(..) =>
+                write!(f, "flush request failed"),
+            Error::WriteBlock(..) =>
+                write!(f, "write block request failed"),
+            Error::ReadBlock(..) =>
+                write!(f, "read block request failed"),
+            Error::DeleteBlock(..) =>
+                write!(f, "delete block request failed"),
+            Error::IterBlocks(..) =>
+                write!(f, "iter blocks request failed"),
+            Error::Resync(..) =>
+                write!(f, "mirror resync failed"),
+            Error::Put(..) =>
+                write!(f, "kv put request failed"),
+            Error::Sharding(..) =>
+                write!(f, "sharded frontend setup failed"),
+            Error::WriteObject(..) =>
+                write!(f, "write object request failed"),
+            Error::ReadObject(..) =>
+                write!(f, "read object request failed"),
+            Error::DeleteObject(..) =>
+                write!(f, "delete object request failed"),
+            Error::Stats(..) =>
+                write!(f, "stats request failed"),
+            Error::Shutdown(..) =>
+                write!(f, "shutdown request failed"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::GenServer(..) =>
+                None,
+            Error::Info(error) =>
+                Some(error),
+            Error::Flush(error) =>
+                Some(error),
+            Error::WriteBlock(error) =>
+                Some(error),
+            Error::ReadBlock(error) =>
+                Some(error),
+            Error::DeleteBlock(error) =>
+                Some(error),
+            Error::IterBlocks(error) =>
+                Some(error),
+            Error::Resync(error) =>
+                Some(error),
+            Error::Put(error) =>
+                Some(error),
+            Error::Sharding(error) =>
+                Some(error),
+            Error::WriteObject(error) =>
+                Some(error),
+            Error::ReadObject(error) =>
+                Some(error),
+            Error::DeleteObject(error) =>
+                Some(error),
+            Error::Stats(error) =>
+                Some(error),
+            Error::Shutdown(error) =>
+                Some(error),
+        }
+    }
+}
+
+impl fmt::Display for InfoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InfoError::GenServer(ero::NoProcError) =>
+                write!(f, "{}", GEN_SERVER_IS_GONE),
+            InfoError::Timeout =>
+                write!(f, "{}", TIMEOUT),
+            InfoError::ThreadPoolWorker =>
+                write!(f, "{}", THREAD_POOL_WORKER),
+        }
+    }
+}
+
+impl std::error::Error for InfoError { }
+
+impl fmt::Display for FlushError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlushError::GenServer(ero::NoProcError) =>
+                write!(f, "{}", GEN_SERVER_IS_GONE),
+            FlushError::Timeout =>
+                write!(f, "{}", TIMEOUT),
+            FlushError::ThreadPoolWorker =>
+                write!(f, "{}", THREAD_POOL_WORKER),
+        }
+    }
+}
+
+impl std::error::Error for FlushError { }
+
+impl fmt::Display for WriteBlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteBlockError::GenServer(ero::NoProcError) =>
+                write!(f, "{}", GEN_SERVER_IS_GONE),
+            WriteBlockError::NoSpaceLeft =>
+                write!(f, "no space left in the wheel"),
+            WriteBlockError::Timeout =>
+                write!(f, "{}", TIMEOUT),
+            WriteBlockError::ThreadPoolWorker =>
+                write!(f, "{}", THREAD_POOL_WORKER),
+            WriteBlockError::OutcomeUnknown =>
+                write!(f, "write request was lost in flight, its outcome is unknown"),
+        }
+    }
+}
+
+impl std::error::Error for WriteBlockError { }
+
+impl fmt::Display for ReadBlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadBlockError::GenServer(ero::NoProcError) =>
+                write!(f, "{}", GEN_SERVER_IS_GONE),
+            ReadBlockError::NotFound =>
+                write!(f, "block not found"),
+            ReadBlockError::Timeout =>
+                write!(f, "{}", TIMEOUT),
+            ReadBlockError::ThreadPoolWorker =>
+                write!(f, "{}", THREAD_POOL_WORKER),
+            ReadBlockError::Corrupted =>
+                write!(f, "block checksum mismatch"),
+            ReadBlockError::DecryptionFailed =>
+                write!(f, "block decryption failed"),
+            ReadBlockError::Undecodable =>
+                write!(f, "block frame could not be decoded"),
+            ReadBlockError::DecodingFailed =>
+                write!(f, "block decoding job failed"),
+        }
+    }
+}
+
+impl std::error::Error for ReadBlockError { }
+
+impl fmt::Display for DeleteBlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeleteBlockError::GenServer(ero::NoProcError) =>
+                write!(f, "{}", GEN_SERVER_IS_GONE),
+            DeleteBlockError::NotFound =>
+                write!(f, "block not found"),
+            DeleteBlockError::Timeout =>
+                write!(f, "{}", TIMEOUT),
+            DeleteBlockError::ThreadPoolWorker =>
+                write!(f, "{}", THREAD_POOL_WORKER),
+        }
+    }
+}
+
+impl std::error::Error for DeleteBlockError { }
+
+impl fmt::Display for IterBlocksError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IterBlocksError::GenServer(ero::NoProcError) =>
+                write!(f, "{}", GEN_SERVER_IS_GONE),
+            IterBlocksError::Timeout =>
+                write!(f, "{}", TIMEOUT),
+            IterBlocksError::ThreadPoolWorker =>
+                write!(f, "{}", THREAD_POOL_WORKER),
+            IterBlocksError::Interrupted =>
+                write!(f, "blocks iteration was interrupted before completion"),
+            IterBlocksError::Corrupted { block_id, } =>
+                write!(f, "block {:?} is corrupted", block_id),
+        }
+    }
+}
+
+impl std::error::Error for IterBlocksError { }
+
+impl fmt::Display for StatsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatsError::GenServer(ero::NoProcError) =>
+                write!(f, "{}", GEN_SERVER_IS_GONE),
+            StatsError::Timeout =>
+                write!(f, "{}", TIMEOUT),
+        }
+    }
+}
+
+impl std::error::Error for StatsError { }
+
+impl fmt::Display for ShutdownError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShutdownError::GenServer(ero::NoProcError) =>
+                write!(f, "{}", GEN_SERVER_IS_GONE),
+            ShutdownError::Timeout =>
+                write!(f, "{}", TIMEOUT),
+        }
+    }
+}
+
+impl std::error::Error for ShutdownError { }
+
+impl fmt::Display for WriteObjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteObjectError::ZeroChunkSize =>
+                write!(f, "object chunk size is zero"),
+            WriteObjectError::WriteBlock(..) =>
+                write!(f, "failed to write object block"),
+        }
+    }
+}
+
+impl std::error::Error for WriteObjectError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WriteObjectError::ZeroChunkSize =>
+                None,
+            WriteObjectError::WriteBlock(error) =>
+                Some(error),
+        }
+    }
+}
+
+impl fmt::Display for ReadObjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadObjectError::ReadBlock(..) =>
+                write!(f, "failed to read object block"),
+            ReadObjectError::InvalidManifest =>
+                write!(f, "block is not a valid object manifest"),
+        }
+    }
+}
+
+impl std::error::Error for ReadObjectError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReadObjectError::ReadBlock(error) =>
+                Some(error),
+            ReadObjectError::InvalidManifest =>
+                None,
+        }
+    }
+}
+
+impl fmt::Display for DeleteObjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeleteObjectError::ReadManifest(..) =>
+                write!(f, "failed to read object manifest"),
+            DeleteObjectError::DeleteBlock(..) =>
+                write!(f, "failed to delete object block"),
+        }
+    }
+}
+
+impl std::error::Error for DeleteObjectError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DeleteObjectError::ReadManifest(error) =>
+                Some(error),
+            DeleteObjectError::DeleteBlock(error) =>
+                Some(error),
+        }
+    }
+}
+
+impl fmt::Display for ResyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResyncError::IterBlocks(..) =>
+                write!(f, "failed to iterate over mirrored blocks"),
+            ResyncError::ReadBlock(..) =>
+                write!(f, "failed to read block to copy"),
+            ResyncError::WriteBlock(..) =>
+                write!(f, "failed to write copied block"),
+        }
+    }
+}
+
+impl std::error::Error for ResyncError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ResyncError::IterBlocks(error) =>
+                Some(error),
+            ResyncError::ReadBlock(error) =>
+                Some(error),
+            ResyncError::WriteBlock(error) =>
+                Some(error),
+        }
+    }
+}
+
+impl fmt::Display for PutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PutError::KeyTooLong =>
+                write!(f, "kv key is too long"),
+            PutError::WriteBlock(..) =>
+                write!(f, "failed to write kv record block"),
+        }
+    }
+}
+
+impl std::error::Error for PutError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PutError::KeyTooLong =>
+                None,
+            PutError::WriteBlock(error) =>
+                Some(error),
+        }
+    }
+}
+
+impl fmt::Display for ShardingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShardingError::NoShards =>
+                write!(f, "no shards given"),
+        }
+    }
+}
+
+impl std::error::Error for ShardingError {}
+
+impl fmt::Display for gen_server::Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            gen_server::Error::BlockwheelFsVersklaven(error) =>
+                write!(f, "failed to start blockwheel-fs meister: {:?}", error),
+            gen_server::Error::FtdVersklaven(error) =>
+                write!(f, "failed to start ftd sklave: {:?}", error),
+            gen_server::Error::RequestInfoBefehl(error) =>
+                write!(f, "blockwheel-fs info request failed: {:?}", error),
+            gen_server::Error::RequestFlushBefehl(error) =>
+                write!(f, "blockwheel-fs flush request failed: {:?}", error),
+            gen_server::Error::RequestWriteBlockBefehl(error) =>
+                write!(f, "blockwheel-fs write block request failed: {:?}", error),
+            gen_server::Error::RequestReadBlockBefehl(error) =>
+                write!(f, "blockwheel-fs read block request failed: {:?}", error),
+            gen_server::Error::RequestDeleteBlockBefehl(error) =>
+                write!(f, "blockwheel-fs delete block request failed: {:?}", error),
+            gen_server::Error::RequestIterBlocksInitBefehl(error) =>
+                write!(f, "blockwheel-fs iter blocks init request failed: {:?}", error),
+            gen_server::Error::RequestIterBlocksNextBefehl(error) =>
+                write!(f, "blockwheel-fs iter blocks next request failed: {:?}", error),
+            gen_server::Error::FtdSklaveIsGoneDuringIterBlocksInit =>
+                write!(f, "ftd sklave is gone during iter blocks init"),
+            gen_server::Error::FtdSklaveIsGoneDuringIterBlocksNext =>
+                write!(f, "ftd sklave is gone during iter blocks next"),
+            gen_server::Error::FtdSklaveIsGoneDuringShutdownFlush =>
+                write!(f, "ftd sklave is gone during shutdown flush"),
+            gen_server::Error::EncodeBlock(error) =>
+                write!(f, "failed to encode block: {:?}", error),
+            gen_server::Error::DecodeBlock(error) =>
+                write!(f, "failed to decode block: {:?}", error),
+            #[cfg(feature = "fault-injection")]
+            gen_server::Error::FaultInjectedOnRequest =>
+                write!(f, "fault injected on request"),
+            #[cfg(feature = "fault-injection")]
+            gen_server::Error::FaultInjectedOnThreadPoolSpawn =>
+                write!(f, "fault injected on thread pool spawn"),
+            #[cfg(feature = "fault-injection")]
+            gen_server::Error::FaultInjectedOnEcho =>
+                write!(f, "fault injected on echo"),
+        }
+    }
+}
+
+impl std::error::Error for gen_server::Error { }
+
+impl From<ero::NoProcError> for Error {
+    fn from(error: ero::NoProcError) -> Error {
+        Error::GenServer(error)
+    }
+}
+
+impl From<InfoError> for Error {
+    fn from(error: InfoError) -> Error {
+        Error::Info(error)
+    }
+}
+
+impl From<FlushError> for Error {
+    fn from(error: FlushError) -> Error {
+        Error::Flush(error)
+    }
+}
+
+impl From<WriteBlockError> for Error {
+    fn from(error: WriteBlockError) -> Error {
+        Error::WriteBlock(error)
+    }
+}
+
+impl From<ReadBlockError> for Error {
+    fn from(error: ReadBlockError) -> Error {
+        Error::ReadBlock(error)
+    }
+}
+
+impl From<DeleteBlockError> for Error {
+    fn from(error: DeleteBlockError) -> Error {
+        Error::DeleteBlock(error)
+    }
+}
+
+impl From<IterBlocksError> for Error {
+    fn from(error: IterBlocksError) -> Error {
+        Error::IterBlocks(error)
+    }
+}
+
+impl From<ResyncError> for Error {
+    fn from(error: ResyncError) -> Error {
+        Error::Resync(error)
+    }
+}
+
+impl From<PutError> for Error {
+    fn from(error: PutError) -> Error {
+        Error::Put(error)
+    }
+}
+
+impl From<ShardingError> for Error {
+    fn from(error: ShardingError) -> Error {
+        Error::Sharding(error)
+    }
+}
+
+impl From<WriteObjectError> for Error {
+    fn from(error: WriteObjectError) -> Error {
+        Error::WriteObject(error)
+    }
+}
+
+impl From<ReadObjectError> for Error {
+    fn from(error: ReadObjectError) -> Error {
+        Error::ReadObject(error)
+    }
+}
+
+impl From<DeleteObjectError> for Error {
+    fn from(error: DeleteObjectError) -> Error {
+        Error::DeleteObject(error)
+    }
+}
+
+impl From<StatsError> for Error {
+    fn from(error: StatsError) -> Error {
+        Error::Stats(error)
+    }
+}
+
+impl From<ShutdownError> for Error {
+    fn from(error: ShutdownError) -> Error {
+        Error::Shutdown(error)
+    }
+}
+
+impl From<proto::ReadBlockError> for ReadBlockError {
+    fn from(error: proto::ReadBlockError) -> ReadBlockError {
+        match error {
+            proto::ReadBlockError::NotFound =>
+                ReadBlockError::NotFound,
+            proto::ReadBlockError::Corrupted =>
+                ReadBlockError::Corrupted,
+            proto::ReadBlockError::DecryptionFailed =>
+                ReadBlockError::DecryptionFailed,
+            proto::ReadBlockError::Undecodable =>
+                ReadBlockError::Undecodable,
+            proto::ReadBlockError::DecodingFailed =>
+                ReadBlockError::DecodingFailed,
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Fault injection for tests, compiled away unless the `fault-injection`
+//! feature is on.
+
+#[cfg(feature = "fault-injection")]
+use std::{
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
+
+/// Kind of failure injected by [`FaultInjector`].
+#[cfg(feature = "fault-injection")]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Fault {
+    /// The gen server fails while handling a request, as if its
+    /// blockwheel-fs meister had failed. The request is lost and the server
+    /// goes through its `RestartPolicy`.
+    FailRequest,
+    /// A reply is dropped without an answer, as if the ftd sklave lost it.
+    /// For requests the `Pid` sees its reply canceled, for blocks iterators
+    /// the next item is lost and the stream is interrupted.
+    CancelReply,
+    /// Spawning a thread pool job fails. For requests the gen server fails,
+    /// for blocks iterators only the iterator does.
+    ThreadPoolSpawn,
+    /// Write requests are rejected with `NoSpaceLeft`.
+    DiskFull,
+    /// A block read from the wheel, by a request or a blocks iterator, gets
+    /// its last byte flipped before it is decoded, as if damaged on disk.
+    CorruptBlock,
+    /// The echo of an info, flush, write or delete request is dropped before
+    /// blockwheel-fs commits it, as if the meister was lost with the request
+    /// in flight: the ftd sklave receives the cancellation and terminates,
+    /// and the gen server fails and goes through its `RestartPolicy`.
+    LoseEcho,
+}
+
+/// Makes the gen server fail on purpose, see
+/// [`crate::GenServer::with_fault_injector`].
+///
+/// Each fault is armed for a number of occurrences and disarms itself once
+/// they are used up. Clones share the same counters, so a test keeps one
+/// clone to arm faults while the server is running.
+#[cfg(feature = "fault-injection")]
+#[derive(Clone, Default, Debug)]
+pub struct FaultInjector {
+    armed: Arc<Mutex<Armed>>,
+}
+
+#[cfg(feature = "fault-injection")]
+#[derive(Default, Debug)]
+struct Armed {
+    fail_request: usize,
+    cancel_reply: usize,
+    thread_pool_spawn: usize,
+    disk_full: usize,
+    corrupt_block: usize,
+    lose_echo: usize,
+}
+
+#[cfg(feature = "fault-injection")]
+impl FaultInjector {
+    pub fn new() -> FaultInjector {
+        FaultInjector::default()
+    }
+
+    /// Arms `fault` for the next `count` occurrences, on top of the ones
+    /// still pending.
+    pub fn inject(&self, fault: Fault, count: usize) {
+        let mut armed = self.armed.lock().unwrap();
+        *armed.counter(fault) += count;
+    }
+
+    /// Number of occurrences of `fault` not used up yet.
+    pub fn pending(&self, fault: Fault) -> usize {
+        *self.armed.lock().unwrap().counter(fault)
+    }
+
+    pub fn clear(&self) {
+        *self.armed.lock().unwrap() = Armed::default();
+    }
+
+    fn take(&self, fault: Fault) -> bool {
+        let mut armed = self.armed.lock().unwrap();
+        let counter = armed.counter(fault);
+        if *counter == 0 {
+            false
+        } else {
+            *counter -= 1;
+            true
+        }
+    }
+}
+
+#[cfg(feature = "fault-injection")]
+impl Armed {
+    fn counter(&mut self, fault: Fault) -> &mut usize {
+        match fault {
+            Fault::FailRequest =>
+                &mut self.fail_request,
+            Fault::CancelReply =>
+                &mut self.cancel_reply,
+            Fault::ThreadPoolSpawn =>
+                &mut self.thread_pool_spawn,
+            Fault::DiskFull =>
+                &mut self.disk_full,
+            Fault::CorruptBlock =>
+                &mut self.corrupt_block,
+            Fault::LoseEcho =>
+                &mut self.lose_echo,
+        }
+    }
+}
+
+/// What the gen server should do with an incoming request, for the faults
+/// which apply to any request.
+#[cfg(feature = "fault-injection")]
+pub(crate) enum RequestFault {
+    Fail,
+    CancelReply,
+    ThreadPoolSpawn,
+}
+
+#[cfg(feature = "fault-injection")]
+#[derive(Clone, Default, Debug)]
+pub(crate) struct Hook(Option<FaultInjector>);
+
+#[cfg(feature = "fault-injection")]
+impl Hook {
+    pub(crate) fn new(fault_injector: FaultInjector) -> Hook {
+        Hook(Some(fault_injector))
+    }
+
+    /// Picks the fault for the next request among the ones which apply to
+    /// any request, see [`Hook::disk_full`] and [`Hook::lose_echo`] for the
+    /// others.
+    pub(crate) fn on_request(&self) -> Option<RequestFault> {
+        if self.take(Fault::FailRequest) {
+            Some(RequestFault::Fail)
+        } else if self.take(Fault::CancelReply) {
+            Some(RequestFault::CancelReply)
+        } else if self.take(Fault::ThreadPoolSpawn) {
+            Some(RequestFault::ThreadPoolSpawn)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn disk_full(&self) -> bool {
+        self.take(Fault::DiskFull)
+    }
+
+    pub(crate) fn lose_echo(&self) -> bool {
+        self.take(Fault::LoseEcho)
+    }
+
+    pub(crate) fn cancel_reply(&self) -> bool {
+        self.take(Fault::CancelReply)
+    }
+
+    pub(crate) fn fail_spawn(&self) -> bool {
+        self.take(Fault::ThreadPoolSpawn)
+    }
+
+    pub(crate) fn corrupt_block(&self) -> bool {
+        self.take(Fault::CorruptBlock)
+    }
+
+    fn take(&self, fault: Fault) -> bool {
+        self.0.as_ref().is_some_and(|fault_injector| fault_injector.take(fault))
+    }
+}
@@ -34,14 +34,16 @@ pub enum Order {
     Flush(komm::Umschlag<Flushed, proto::RequestFlushReplyTx>),
     WriteBlockCancel(komm::UmschlagAbbrechen<proto::RequestWriteBlockReplyTx>),
     WriteBlock(komm::Umschlag<Result<block::Id, RequestWriteBlockError>, proto::RequestWriteBlockReplyTx>),
-    ReadBlockCancel(komm::UmschlagAbbrechen<proto::RequestReadBlockReplyTx>),
-    ReadBlock(komm::Umschlag<Result<Bytes, RequestReadBlockError>, proto::RequestReadBlockReplyTx>),
+    ReadBlockCancel(komm::UmschlagAbbrechen<proto::MeisterReadBlockReplyTx>),
+    ReadBlock(komm::Umschlag<Result<Bytes, RequestReadBlockError>, proto::MeisterReadBlockReplyTx>),
     DeleteBlockCancel(komm::UmschlagAbbrechen<proto::RequestDeleteBlockReplyTx>),
     DeleteBlock(komm::Umschlag<Result<Deleted, RequestDeleteBlockError>, proto::RequestDeleteBlockReplyTx>),
     IterBlocksInitCancel(komm::UmschlagAbbrechen<RequestIterBlocksInit>),
     IterBlocksInit(komm::Umschlag<blockwheel_fs::IterBlocks, RequestIterBlocksInit>),
     IterBlocksNextCancel(komm::UmschlagAbbrechen<RequestIterBlocksNext>),
     IterBlocksNext(komm::Umschlag<blockwheel_fs::IterBlocksItem, RequestIterBlocksNext>),
+    IterBlockIdsNextCancel(komm::UmschlagAbbrechen<RequestIterBlockIdsNext>),
+    IterBlockIdsNext(komm::Umschlag<IterBlockIdsNextItem, RequestIterBlockIdsNext>),
 }
 
 pub struct RequestIterBlocksInit {
@@ -52,6 +54,51 @@ pub struct RequestIterBlocksNext {
     pub iter_blocks_next_tx: oneshot::Sender<blockwheel_fs::IterBlocksItem>,
 }
 
+pub struct RequestIterBlockIdsNext {
+    pub iter_block_ids_next_tx: oneshot::Sender<IterBlockIdsNextItem>,
+}
+
+/// Echo of the next blocks iterator item.
+///
+/// In `BlockIds` mode only the id and the size of the block are committed,
+/// so the payload is dropped right where blockwheel-fs hands it over and
+/// never travels through this sklave.
+pub enum IterBlocksNextEcho {
+    Blocks(komm::Rueckkopplung<Order, RequestIterBlocksNext>),
+    BlockIds(komm::Rueckkopplung<Order, RequestIterBlockIdsNext>),
+}
+
+impl komm::Echo<blockwheel_fs::IterBlocksItem> for IterBlocksNextEcho {
+    fn commit_echo(self, iter_blocks_item: blockwheel_fs::IterBlocksItem) -> Result<(), komm::EchoError> {
+        match self {
+            IterBlocksNextEcho::Blocks(rueckkopplung) =>
+                rueckkopplung.commit_echo(iter_blocks_item),
+            IterBlocksNextEcho::BlockIds(rueckkopplung) => {
+                let item = match iter_blocks_item {
+                    blockwheel_fs::IterBlocksItem::Block { block_id, block_bytes, iterator_next, } =>
+                        IterBlockIdsNextItem::BlockId {
+                            block_id,
+                            block_size: block_bytes.len(),
+                            iterator_next,
+                        },
+                    blockwheel_fs::IterBlocksItem::NoMoreBlocks =>
+                        IterBlockIdsNextItem::NoMoreBlocks,
+                };
+                rueckkopplung.commit_echo(item)
+            },
+        }
+    }
+}
+
+pub enum IterBlockIdsNextItem {
+    BlockId {
+        block_id: block::Id,
+        block_size: usize,
+        iterator_next: blockwheel_fs::IterBlocksIterator,
+    },
+    NoMoreBlocks,
+}
+
 pub struct Welt;
 
 #[derive(Debug)]
@@ -64,6 +111,7 @@ pub enum Error {
     GenServerIsLostOnRequestDeleteBlock,
     GenServerIsLostOnRequestIterBlocksInit,
     GenServerIsLostOnRequestIterBlocksNext,
+    GenServerIsLostOnRequestIterBlockIdsNext,
 }
 
 pub fn job<J>(sklave_job: SklaveJob, thread_pool: &edeltraud::Handle<J>) {
@@ -135,6 +183,15 @@ fn run_job<J>(mut sklave_job: SklaveJob, _thread_pool: &edeltraud::Handle<J>) ->
                             if let Err(_send_error) = iter_blocks_next_tx.send(iter_blocks_item) {
                                 log::debug!("iter blocks stream process is gone during RequestIterBlocksNext");
                             },
+                        Order::IterBlockIdsNextCancel(komm::UmschlagAbbrechen { .. }) =>
+                            return Err(Error::GenServerIsLostOnRequestIterBlockIdsNext),
+                        Order::IterBlockIdsNext(komm::Umschlag {
+                            inhalt: iter_block_ids_item,
+                            stamp: RequestIterBlockIdsNext { iter_block_ids_next_tx, },
+                        }) =>
+                            if let Err(_send_error) = iter_block_ids_next_tx.send(iter_block_ids_item) {
+                                log::debug!("iter block ids stream process is gone during RequestIterBlockIdsNext");
+                            },
                     }
                 },
                 arbeitssklave::SklavenBefehl::Ende { sklave_job: next_sklave_job, } => {
@@ -182,14 +239,14 @@ impl From<komm::Umschlag<Result<block::Id, RequestWriteBlockError>, proto::Reque
     }
 }
 
-impl From<komm::UmschlagAbbrechen<proto::RequestReadBlockReplyTx>> for Order {
-    fn from(v: komm::UmschlagAbbrechen<proto::RequestReadBlockReplyTx>) -> Order {
+impl From<komm::UmschlagAbbrechen<proto::MeisterReadBlockReplyTx>> for Order {
+    fn from(v: komm::UmschlagAbbrechen<proto::MeisterReadBlockReplyTx>) -> Order {
         Order::ReadBlockCancel(v)
     }
 }
 
-impl From<komm::Umschlag<Result<Bytes, RequestReadBlockError>, proto::RequestReadBlockReplyTx>> for Order {
-    fn from(v: komm::Umschlag<Result<Bytes, RequestReadBlockError>, proto::RequestReadBlockReplyTx>) -> Order {
+impl From<komm::Umschlag<Result<Bytes, RequestReadBlockError>, proto::MeisterReadBlockReplyTx>> for Order {
+    fn from(v: komm::Umschlag<Result<Bytes, RequestReadBlockError>, proto::MeisterReadBlockReplyTx>) -> Order {
         Order::ReadBlock(v)
     }
 }
@@ -229,3 +286,15 @@ impl From<komm::Umschlag<blockwheel_fs::IterBlocksItem, RequestIterBlocksNext>>
         Order::IterBlocksNext(v)
     }
 }
+
+impl From<komm::UmschlagAbbrechen<RequestIterBlockIdsNext>> for Order {
+    fn from(v: komm::UmschlagAbbrechen<RequestIterBlockIdsNext>) -> Order {
+        Order::IterBlockIdsNextCancel(v)
+    }
+}
+
+impl From<komm::Umschlag<IterBlockIdsNextItem, RequestIterBlockIdsNext>> for Order {
+    fn from(v: komm::Umschlag<IterBlockIdsNextItem, RequestIterBlockIdsNext>) -> Order {
+        Order::IterBlockIdsNext(v)
+    }
+}
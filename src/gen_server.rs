@@ -1,15 +1,40 @@
+use std::{
+    collections::{
+        BTreeSet,
+    },
+    sync::{
+        atomic::{
+            Ordering,
+        },
+        Arc,
+    },
+    time::{
+        Instant,
+    },
+};
+
 use futures::{
     channel::{
         mpsc,
         oneshot,
     },
-    stream,
+    future::{
+        self,
+        BoxFuture,
+    },
+    stream::{
+        self,
+        FuturesUnordered,
+    },
+    select,
     SinkExt,
     StreamExt,
+    FutureExt,
 };
 
 use alloc_pool::{
     bytes::{
+        Bytes,
         BytesPool,
     },
 };
@@ -18,25 +43,51 @@ use arbeitssklave::{
     komm,
 };
 
+use tokio::{
+    sync::{
+        watch,
+    },
+};
+
 use ero::{
-    restart,
     supervisor::{
         SupervisorPid,
     },
-    ErrorSeverity,
-    RestartStrategy,
 };
 
 use crate::{
     proto,
+    trace,
+    stats,
+    codec,
     ftd_sklave,
+    restart_policy,
     echo_policy::{
         EchoPolicy,
     },
+    write_tokens::{
+        WriteTokens,
+    },
+    block,
     Params,
+    Health,
+    Flushed,
     IterBlocks,
     IterBlocksItem,
+    IterBlocksCursor,
+    IterBlockIds,
+    IterBlockIdsItem,
+    Terminated,
+    ScrubReport,
+    RestartPolicy,
     InterpreterParams,
+    RequestReadBlockError,
+};
+
+#[cfg(feature = "fault-injection")]
+use crate::{
+    fault,
+    RequestWriteBlockError,
 };
 
 #[derive(Debug)]
@@ -52,6 +103,26 @@ pub enum Error {
     RequestIterBlocksNextBefehl(blockwheel_fs::Error),
     FtdSklaveIsGoneDuringIterBlocksInit,
     FtdSklaveIsGoneDuringIterBlocksNext,
+    FtdSklaveIsGoneDuringShutdownFlush,
+    EncodeBlock(codec::Error),
+    DecodeBlock(codec::Error),
+    #[cfg(feature = "fault-injection")]
+    FaultInjectedOnRequest,
+    #[cfg(feature = "fault-injection")]
+    FaultInjectedOnThreadPoolSpawn,
+    #[cfg(feature = "fault-injection")]
+    FaultInjectedOnEcho,
+}
+
+/// Settings and reporting handles of the gen server, kept as is across
+/// restarts.
+pub struct GenServerConfig {
+    pub restart_policy: RestartPolicy,
+    pub codec_settings: codec::Settings,
+    pub counters: Arc<stats::Counters>,
+    pub health_tx: watch::Sender<Health>,
+    #[cfg(feature = "fault-injection")]
+    pub fault_hook: fault::Hook,
 }
 
 pub async fn run<J>(
@@ -60,46 +131,71 @@ pub async fn run<J>(
     params: Params,
     blocks_pool: BytesPool,
     thread_pool: edeltraud::Handle<J>,
+    config: GenServerConfig,
 )
 where J: From<blockwheel_fs::job::SklaveJob<EchoPolicy>>,
       J: From<ftd_sklave::SklaveJob>,
+      J: From<codec::CodecJob>,
       J: Send + 'static,
 {
-    let terminate_result =
-        restart::restartable(
-            ero::Params {
-                name: format!(
-                    "blockwheel_fs on {:?}",
-                    match params.interpreter {
-                        InterpreterParams::FixedFile(ref interpreter_params) =>
-                            format!("fixed file: {:?}", interpreter_params.wheel_filename),
-                        InterpreterParams::Ram(ref interpreter_params) =>
-                            format!("ram file of {} bytes", interpreter_params.init_wheel_size_bytes),
-                        InterpreterParams::Dummy(ref interpreter_params) =>
-                            format!("dummy file of {} bytes", interpreter_params.init_wheel_size_bytes),
-                    },
-                ),
-                restart_strategy: RestartStrategy::InstantCrash,
+    let name = format!(
+        "blockwheel_fs on {:?}",
+        match params.interpreter {
+            InterpreterParams::FixedFile(ref interpreter_params) =>
+                format!("fixed file: {:?}", interpreter_params.wheel_filename),
+            InterpreterParams::Ram(ref interpreter_params) =>
+                format!("ram file of {} bytes", interpreter_params.init_wheel_size_bytes),
+            InterpreterParams::Dummy(ref interpreter_params) =>
+                format!("dummy file of {} bytes", interpreter_params.init_wheel_size_bytes),
+        },
+    );
+    let GenServerConfig { restart_policy, codec_settings, counters, health_tx, #[cfg(feature = "fault-injection")] fault_hook, } = config;
+    let mut state = State {
+        parent_supervisor,
+        params,
+        blocks_pool,
+        thread_pool,
+        fused_request_rx,
+        codec_settings,
+        counters,
+        health_tx,
+        #[cfg(feature = "fault-injection")]
+        fault_hook,
+        write_tokens: WriteTokens::default(),
+    };
+    let mut restart_tracker = restart_policy::Tracker::new(restart_policy);
+
+    loop {
+        let child_supervisor_gen_server = state.parent_supervisor.child_supervisor();
+        let child_supervisor_pid = child_supervisor_gen_server.pid();
+        state.parent_supervisor.spawn_link_temporary(
+            child_supervisor_gen_server.run(),
+        );
+
+        let started_at = Instant::now();
+        let error = match busyloop_init(child_supervisor_pid, &mut state).await {
+            Ok(()) => {
+                state.health_tx.send_replace(Health::Terminated);
+                return;
             },
-            State {
-                parent_supervisor,
-                params,
-                blocks_pool,
-                thread_pool,
-                fused_request_rx,
+            Err(error) =>
+                error,
+        };
+
+        match restart_tracker.decide(started_at.elapsed()) {
+            restart_policy::Decision::Crash => {
+                log::error!("{} fatal error: {:?}", name, error);
+                state.health_tx.send_replace(Health::Failed { reason: Arc::new(error), });
+                return;
             },
-            |mut state| async move {
-                let child_supervisor_gen_server = state.parent_supervisor.child_supervisor();
-                let child_supervisor_pid = child_supervisor_gen_server.pid();
-                state.parent_supervisor.spawn_link_temporary(
-                    child_supervisor_gen_server.run(),
-                );
-                busyloop_init(child_supervisor_pid, state).await
+            restart_policy::Decision::RestartAfter(delay) => {
+                log::warn!("{} crashed with error: {:?}, restarting in {:?}", name, error, delay);
+                state.health_tx.send_replace(Health::Restarting { reason: Arc::new(error), });
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
             },
-        )
-        .await;
-    if let Err(error) = terminate_result {
-        log::error!("fatal error: {:?}", error);
+        }
     }
 }
 
@@ -109,17 +205,18 @@ struct State<J> {
     blocks_pool: BytesPool,
     thread_pool: edeltraud::Handle<J>,
     fused_request_rx: stream::Fuse<mpsc::Receiver<proto::Request>>,
+    codec_settings: codec::Settings,
+    counters: Arc<stats::Counters>,
+    health_tx: watch::Sender<Health>,
+    #[cfg(feature = "fault-injection")]
+    fault_hook: fault::Hook,
+    write_tokens: WriteTokens,
 }
 
-impl<J> From<Error> for ErrorSeverity<State<J>, Error> {
-    fn from(error: Error) -> Self {
-        ErrorSeverity::Fatal(error)
-    }
-}
-
-async fn busyloop_init<J>(supervisor_pid: SupervisorPid, state: State<J>) -> Result<(), ErrorSeverity<State<J>, Error>>
+async fn busyloop_init<J>(supervisor_pid: SupervisorPid, state: &mut State<J>) -> Result<(), Error>
 where J: From<blockwheel_fs::job::SklaveJob<EchoPolicy>>,
       J: From<ftd_sklave::SklaveJob>,
+      J: From<codec::CodecJob>,
       J: Send + 'static,
 {
     let blockwheel_fs_meister =
@@ -134,6 +231,7 @@ where J: From<blockwheel_fs::job::SklaveJob<EchoPolicy>>,
         .map_err(Error::FtdVersklaven)?;
     let ftd_sendegeraet =
         komm::Sendegeraet::starten(&ftd_sklave_meister, state.thread_pool.clone());
+    state.health_tx.send_replace(Health::Running);
 
     busyloop(
         supervisor_pid,
@@ -146,19 +244,57 @@ where J: From<blockwheel_fs::job::SklaveJob<EchoPolicy>>,
 
 async fn busyloop<J>(
     mut supervisor_pid: SupervisorPid,
-    mut state: State<J>,
+    state: &mut State<J>,
     blockwheel_fs_meister: blockwheel_fs::Meister<EchoPolicy>,
     _ftd_sklave_meister: arbeitssklave::Meister<ftd_sklave::Welt, ftd_sklave::Order>,
     ftd_sendegeraet: komm::Sendegeraet<ftd_sklave::Order>,
 )
-    -> Result<(), ErrorSeverity<State<J>, Error>>
+    -> Result<(), Error>
 where J: From<blockwheel_fs::job::SklaveJob<EchoPolicy>>,
       J: From<ftd_sklave::SklaveJob>,
+      J: From<codec::CodecJob>,
       J: Send + 'static,
 {
-    while let Some(request) = state.fused_request_rx.next().await {
+    let (iter_blocks_active_tx, mut iter_blocks_active_rx) = mpsc::channel::<()>(0);
+    let (iter_blocks_cancel_tx, iter_blocks_cancel_rx) = oneshot::channel::<()>();
+    let iter_blocks_cancel_rx = iter_blocks_cancel_rx.shared();
+    let mut codec_jobs = CodecJobs::default();
+    let mut shutdown_reply_txs = Vec::new();
+    let mut iterators_deadline = None;
+
+    loop {
+        let request = select! {
+            maybe_request = state.fused_request_rx.next() =>
+                match maybe_request {
+                    Some(request) =>
+                        request,
+                    None =>
+                        break,
+                },
+            (write_token, write_result) = state.write_tokens.pending.select_next_some() => {
+                state.write_tokens.complete(write_token, write_result);
+                continue;
+            },
+            encoded_block = codec_jobs.encodes.select_next_some() => {
+                write_encoded_block(state, &mut codec_jobs, &blockwheel_fs_meister, &ftd_sendegeraet, encoded_block)?;
+                continue;
+            },
+            () = codec_jobs.reads.select_next_some() =>
+                continue,
+        };
+
+        #[cfg(feature = "fault-injection")]
+        let request = match inject_fault(state, &ftd_sendegeraet, request)? {
+            Some(request) =>
+                request,
+            None =>
+                continue,
+        };
+
         match request {
             proto::Request::Info(proto::RequestInfo { reply_tx, }) => {
+                reply_tx.span().event("dispatching to blockwheel-fs");
+                state.counters.info.request(1);
                 blockwheel_fs_meister
                     .info(
                         ftd_sendegeraet.rueckkopplung(reply_tx),
@@ -167,32 +303,64 @@ where J: From<blockwheel_fs::job::SklaveJob<EchoPolicy>>,
                     .map_err(Error::RequestInfoBefehl)?;
             },
             proto::Request::Flush(proto::RequestFlush { reply_tx, }) => {
-                blockwheel_fs_meister
-                    .flush(
-                        ftd_sendegeraet.rueckkopplung(reply_tx),
-                        &state.thread_pool,
-                    )
-                    .map_err(Error::RequestFlushBefehl)?;
+                state.counters.flush.request(1);
+                if codec_jobs.encoding.is_empty() {
+                    reply_tx.span().event("dispatching to blockwheel-fs");
+                    blockwheel_fs_meister
+                        .flush(
+                            ftd_sendegeraet.rueckkopplung(reply_tx),
+                            &state.thread_pool,
+                        )
+                        .map_err(Error::RequestFlushBefehl)?;
+                } else {
+                    reply_tx.span().event("waiting for blocks being encoded");
+                    codec_jobs.deferred_flushes.push((codec_jobs.next_encode_seq, reply_tx));
+                }
             },
             proto::Request::WriteBlock(proto::RequestWriteBlock { block_bytes, reply_tx, }) => {
-                blockwheel_fs_meister
-                    .write_block(
+                reply_tx.span().event("dispatching to blockwheel-fs");
+                state.counters.write_block.request(1);
+                dispatch_write_block(
+                    state,
+                    &mut codec_jobs,
+                    &blockwheel_fs_meister,
+                    &ftd_sendegeraet,
+                    block_bytes,
+                    reply_tx,
+                )?;
+            },
+            proto::Request::WriteBlockWithToken(proto::RequestWriteBlockWithToken { write_token, block_bytes, reply_tx, }) => {
+                reply_tx.span().event("dispatching to blockwheel-fs");
+                state.counters.write_block.request(1);
+                let span = reply_tx.span().clone();
+                if state.write_tokens.register(write_token, reply_tx) {
+                    let (write_tx, write_rx) = oneshot::channel();
+                    dispatch_write_block(
+                        state,
+                        &mut codec_jobs,
+                        &blockwheel_fs_meister,
+                        &ftd_sendegeraet,
                         block_bytes,
-                        ftd_sendegeraet.rueckkopplung(reply_tx),
-                        &state.thread_pool,
-                    )
-                    .map_err(Error::RequestWriteBlockBefehl)?;
+                        proto::ReplyTx::new(write_tx, span),
+                    )?;
+                    state.write_tokens.track(write_token, write_rx);
+                }
             },
             proto::Request::ReadBlock(proto::RequestReadBlock { block_id, reply_tx, }) => {
-                blockwheel_fs_meister
-                    .read_block(
-                        block_id,
-                        ftd_sendegeraet.rueckkopplung(reply_tx),
-                        &state.thread_pool,
-                    )
-                    .map_err(Error::RequestReadBlockBefehl)?;
+                reply_tx.span().event("dispatching to blockwheel-fs");
+                state.counters.read_block.request(1);
+                dispatch_read_block(
+                    state,
+                    &mut codec_jobs,
+                    &blockwheel_fs_meister,
+                    &ftd_sendegeraet,
+                    block_id,
+                    reply_tx,
+                )?;
             },
             proto::Request::DeleteBlock(proto::RequestDeleteBlock { block_id, reply_tx, }) => {
+                reply_tx.span().event("dispatching to blockwheel-fs");
+                state.counters.delete_block.request(1);
                 blockwheel_fs_meister
                     .delete_block(
                         block_id,
@@ -201,45 +369,559 @@ where J: From<blockwheel_fs::job::SklaveJob<EchoPolicy>>,
                     )
                     .map_err(Error::RequestDeleteBlockBefehl)?;
             },
-            proto::Request::IterBlocks(proto::RequestIterBlocks { reply_tx, }) => {
+            proto::Request::IterBlocks(proto::RequestIterBlocks { iterator_next, reply_tx, }) => {
+                reply_tx.span().event("dispatching to blockwheel-fs");
+                state.counters.iter_blocks.request(1);
+                state.counters.active_iterators.fetch_add(1, Ordering::Relaxed);
                 let blockwheel_fs_meister = blockwheel_fs_meister.clone();
                 let ftd_sendegeraet = ftd_sendegeraet.clone();
-                let thread_pool = state.thread_pool.clone();
+                let iter_blocks_env = IterBlocksEnv {
+                    thread_pool: state.thread_pool.clone(),
+                    blocks_pool: state.blocks_pool.clone(),
+                    codec_settings: state.codec_settings.clone(),
+                    counters: state.counters.clone(),
+                    #[cfg(feature = "fault-injection")]
+                    fault_hook: state.fault_hook.clone(),
+                    iter_blocks_cancel_rx: iter_blocks_cancel_rx.clone(),
+                };
+                let iter_blocks_active_tx = iter_blocks_active_tx.clone();
                 supervisor_pid.spawn_link_temporary(async move {
-                    if let Err(error) = iter_blocks_loop(blockwheel_fs_meister, ftd_sendegeraet, reply_tx, &thread_pool).await {
+                    if let Err(error) = iter_blocks_loop(blockwheel_fs_meister, ftd_sendegeraet, iterator_next, reply_tx, &iter_blocks_env).await {
                         log::warn!("blocks iterator loop exited with error: {:?}", error);
                     }
+                    iter_blocks_env.counters.active_iterators.fetch_sub(1, Ordering::Relaxed);
+                    drop(iter_blocks_active_tx);
                 });
             },
+            proto::Request::IterBlockIds(proto::RequestIterBlockIds { reply_tx, }) => {
+                reply_tx.span().event("dispatching to blockwheel-fs");
+                state.counters.iter_blocks.request(1);
+                state.counters.active_iterators.fetch_add(1, Ordering::Relaxed);
+                let blockwheel_fs_meister = blockwheel_fs_meister.clone();
+                let ftd_sendegeraet = ftd_sendegeraet.clone();
+                let thread_pool = state.thread_pool.clone();
+                let counters = state.counters.clone();
+                let iter_blocks_active_tx = iter_blocks_active_tx.clone();
+                let iter_blocks_cancel_rx = iter_blocks_cancel_rx.clone();
+                supervisor_pid.spawn_link_temporary(async move {
+                    if let Err(error) = iter_block_ids_loop(blockwheel_fs_meister, ftd_sendegeraet, reply_tx, &thread_pool, iter_blocks_cancel_rx).await {
+                        log::warn!("block ids iterator loop exited with error: {:?}", error);
+                    }
+                    counters.active_iterators.fetch_sub(1, Ordering::Relaxed);
+                    drop(iter_blocks_active_tx);
+                });
+            },
+            proto::Request::Scrub(proto::RequestScrub { reply_tx, }) => {
+                reply_tx.span().event("dispatching to blockwheel-fs");
+                state.counters.iter_blocks.request(1);
+                state.counters.active_iterators.fetch_add(1, Ordering::Relaxed);
+                let blockwheel_fs_meister = blockwheel_fs_meister.clone();
+                let ftd_sendegeraet = ftd_sendegeraet.clone();
+                let scrub_env = IterBlocksEnv {
+                    thread_pool: state.thread_pool.clone(),
+                    blocks_pool: state.blocks_pool.clone(),
+                    codec_settings: state.codec_settings.clone(),
+                    counters: state.counters.clone(),
+                    #[cfg(feature = "fault-injection")]
+                    fault_hook: state.fault_hook.clone(),
+                    iter_blocks_cancel_rx: iter_blocks_cancel_rx.clone(),
+                };
+                let iter_blocks_active_tx = iter_blocks_active_tx.clone();
+                supervisor_pid.spawn_link_temporary(async move {
+                    if let Err(error) = scrub_loop(blockwheel_fs_meister, ftd_sendegeraet, reply_tx, &scrub_env).await {
+                        log::warn!("scrub loop exited with error: {:?}", error);
+                    }
+                    scrub_env.counters.active_iterators.fetch_sub(1, Ordering::Relaxed);
+                    drop(iter_blocks_active_tx);
+                });
+            },
+            proto::Request::Stats(proto::RequestStats { reply_tx, }) =>
+                if let Err(_send_error) = reply_tx.send(state.counters.snapshot()) {
+                    log::debug!("client is gone during RequestStats");
+                },
+            proto::Request::WriteBlocks(proto::RequestWriteBlocks { blocks_bytes, reply_tx, }) => {
+                reply_tx.span().event("dispatching to blockwheel-fs");
+                state.counters.write_block.request(blocks_bytes.len());
+                let mut item_rxs = Vec::with_capacity(blocks_bytes.len());
+                for block_bytes in blocks_bytes {
+                    let (item_tx, item_rx) = oneshot::channel();
+                    dispatch_write_block(
+                        state,
+                        &mut codec_jobs,
+                        &blockwheel_fs_meister,
+                        &ftd_sendegeraet,
+                        block_bytes,
+                        proto::ReplyTx::new(item_tx, reply_tx.span().clone()),
+                    )?;
+                    item_rxs.push(item_rx);
+                }
+                spawn_batch_reply(&mut supervisor_pid, item_rxs, reply_tx);
+            },
+            proto::Request::ReadBlocks(proto::RequestReadBlocks { block_ids, reply_tx, }) => {
+                reply_tx.span().event("dispatching to blockwheel-fs");
+                state.counters.read_block.request(block_ids.len());
+                let mut item_rxs = Vec::with_capacity(block_ids.len());
+                for block_id in block_ids {
+                    let (item_tx, item_rx) = oneshot::channel();
+                    dispatch_read_block(
+                        state,
+                        &mut codec_jobs,
+                        &blockwheel_fs_meister,
+                        &ftd_sendegeraet,
+                        block_id,
+                        proto::ReplyTx::new(item_tx, reply_tx.span().clone()),
+                    )?;
+                    item_rxs.push(item_rx);
+                }
+                spawn_batch_reply(&mut supervisor_pid, item_rxs, reply_tx);
+            },
+            proto::Request::DeleteBlocks(proto::RequestDeleteBlocks { block_ids, reply_tx, }) => {
+                reply_tx.span().event("dispatching to blockwheel-fs");
+                state.counters.delete_block.request(block_ids.len());
+                let mut item_rxs = Vec::with_capacity(block_ids.len());
+                for block_id in block_ids {
+                    let (item_tx, item_rx) = oneshot::channel();
+                    blockwheel_fs_meister
+                        .delete_block(
+                            block_id,
+                            ftd_sendegeraet.rueckkopplung(proto::ReplyTx::new(item_tx, reply_tx.span().clone())),
+                            &state.thread_pool,
+                        )
+                        .map_err(Error::RequestDeleteBlockBefehl)?;
+                    item_rxs.push(item_rx);
+                }
+                spawn_batch_reply(&mut supervisor_pid, item_rxs, reply_tx);
+            },
+            proto::Request::Shutdown(proto::RequestShutdown { iterators_deadline: requested_deadline, reply_tx, }) => {
+                log::debug!("shutdown requested: closing request channel and draining pending requests");
+                state.fused_request_rx.get_mut().close();
+                // the most impatient requester bounds the wait for iterators
+                iterators_deadline = match (iterators_deadline, requested_deadline) {
+                    (Some(deadline), Some(requested_deadline)) =>
+                        Some(std::cmp::min(deadline, requested_deadline)),
+                    (deadline, requested_deadline) =>
+                        deadline.or(requested_deadline),
+                };
+                shutdown_reply_txs.push(reply_tx);
+            },
+        }
+    }
+
+    // blocks still being encoded are written before the final flush
+    loop {
+        select! {
+            encoded_block = codec_jobs.encodes.select_next_some() =>
+                write_encoded_block(state, &mut codec_jobs, &blockwheel_fs_meister, &ftd_sendegeraet, encoded_block)?,
+            () = codec_jobs.reads.select_next_some() =>
+                (),
+            (write_token, write_result) = state.write_tokens.pending.select_next_some() =>
+                state.write_tokens.complete(write_token, write_result),
+            complete =>
+                break,
+        }
+    }
+
+    if shutdown_reply_txs.is_empty() {
+        log::debug!("request channel is depleted: terminating busyloop");
+        return Ok(());
+    }
+
+    // accepted iterators are allowed to reach the end, but a client may hold
+    // an iterator stream without reading it, so the ones still running at
+    // the deadline are closed
+    log::debug!("request channel is drained: waiting for active blocks iterators");
+    drop(iter_blocks_active_tx);
+    let iterators_finished = {
+        let iterators_drain = async {
+            while iter_blocks_active_rx.next().await.is_some() {}
+        };
+        match iterators_deadline {
+            None => {
+                iterators_drain.await;
+                true
+            },
+            Some(deadline) =>
+                tokio::time::timeout_at(deadline, iterators_drain).await.is_ok(),
+        }
+    };
+    if !iterators_finished {
+        log::debug!("shutdown deadline has passed: closing active blocks iterators");
+        drop(iter_blocks_cancel_tx);
+        while iter_blocks_active_rx.next().await.is_some() {}
+    }
+
+    log::debug!("performing final flush before shutdown");
+    let (flush_reply_tx, flush_reply_rx) = oneshot::channel();
+    blockwheel_fs_meister
+        .flush(
+            ftd_sendegeraet.rueckkopplung(proto::ReplyTx::new(flush_reply_tx, trace::Span::none())),
+            &state.thread_pool,
+        )
+        .map_err(Error::RequestFlushBefehl)?;
+    let Flushed = flush_reply_rx.await
+        .map_err(|oneshot::Canceled| Error::FtdSklaveIsGoneDuringShutdownFlush)?;
+
+    for reply_tx in shutdown_reply_txs {
+        if let Err(_send_error) = reply_tx.send(Terminated) {
+            log::debug!("client is gone during RequestShutdown");
+        }
+    }
+
+    log::debug!("shutdown complete: terminating busyloop");
+    Ok(())
+}
+
+/// Applies the fault armed for `request`, if any. Returns the request back
+/// when it should be served as usual.
+#[cfg(feature = "fault-injection")]
+fn inject_fault<J>(
+    state: &State<J>,
+    ftd_sendegeraet: &komm::Sendegeraet<ftd_sklave::Order>,
+    request: proto::Request,
+)
+    -> Result<Option<proto::Request>, Error>
+{
+    // requests which do not touch the wheel are left alone
+    if let proto::Request::Stats(..) | proto::Request::Shutdown(..) = request {
+        return Ok(Some(request));
+    }
+    match state.fault_hook.on_request() {
+        None =>
+            (),
+        Some(fault::RequestFault::Fail) =>
+            return Err(Error::FaultInjectedOnRequest),
+        Some(fault::RequestFault::ThreadPoolSpawn) =>
+            return Err(Error::FaultInjectedOnThreadPoolSpawn),
+        Some(fault::RequestFault::CancelReply) => {
+            log::debug!("fault injection: dropping request reply");
+            return Ok(None);
+        },
+    }
+    // dropping an uncommitted rueckkopplung sends its cancellation to the ftd
+    // sklave, which is how a lost echo is simulated
+    match request {
+        proto::Request::WriteBlock(proto::RequestWriteBlock { reply_tx, .. }) if state.fault_hook.disk_full() => {
+            if let Err(_send_error) = reply_tx.send(Err(RequestWriteBlockError::NoSpaceLeft)) {
+                log::debug!("client is gone during RequestWriteBlock");
+            }
+            Ok(None)
+        },
+        proto::Request::WriteBlockWithToken(proto::RequestWriteBlockWithToken { reply_tx, .. }) if state.fault_hook.disk_full() => {
+            if let Err(_send_error) = reply_tx.send(Err(proto::WriteBlockWithTokenError::NoSpaceLeft)) {
+                log::debug!("client is gone during RequestWriteBlockWithToken");
+            }
+            Ok(None)
+        },
+        proto::Request::WriteBlocks(proto::RequestWriteBlocks { blocks_bytes, reply_tx, }) if state.fault_hook.disk_full() => {
+            let items = blocks_bytes.iter()
+                .map(|_| Some(Err(RequestWriteBlockError::NoSpaceLeft)))
+                .collect();
+            if let Err(_send_error) = reply_tx.send(items) {
+                log::debug!("client canceled batch request");
+            }
+            Ok(None)
+        },
+        proto::Request::Info(proto::RequestInfo { reply_tx, }) if state.fault_hook.lose_echo() => {
+            log::debug!("fault injection: dropping request echo");
+            drop(ftd_sendegeraet.rueckkopplung(reply_tx));
+            Err(Error::FaultInjectedOnEcho)
+        },
+        proto::Request::Flush(proto::RequestFlush { reply_tx, }) if state.fault_hook.lose_echo() => {
+            log::debug!("fault injection: dropping request echo");
+            drop(ftd_sendegeraet.rueckkopplung(reply_tx));
+            Err(Error::FaultInjectedOnEcho)
+        },
+        proto::Request::WriteBlock(proto::RequestWriteBlock { reply_tx, .. }) if state.fault_hook.lose_echo() => {
+            log::debug!("fault injection: dropping request echo");
+            drop(ftd_sendegeraet.rueckkopplung(reply_tx));
+            Err(Error::FaultInjectedOnEcho)
+        },
+        proto::Request::DeleteBlock(proto::RequestDeleteBlock { reply_tx, .. }) if state.fault_hook.lose_echo() => {
+            log::debug!("fault injection: dropping request echo");
+            drop(ftd_sendegeraet.rueckkopplung(reply_tx));
+            Err(Error::FaultInjectedOnEcho)
+        },
+        request @ (
+            proto::Request::Info(..) |
+            proto::Request::Flush(..) |
+            proto::Request::WriteBlock(..) |
+            proto::Request::WriteBlockWithToken(..) |
+            proto::Request::ReadBlock(..) |
+            proto::Request::DeleteBlock(..) |
+            proto::Request::IterBlocks(..) |
+            proto::Request::IterBlockIds(..) |
+            proto::Request::WriteBlocks(..) |
+            proto::Request::ReadBlocks(..) |
+            proto::Request::DeleteBlocks(..) |
+            proto::Request::Scrub(..) |
+            proto::Request::Stats(..) |
+            proto::Request::Shutdown(..)
+        ) =>
+            Ok(Some(request)),
+    }
+}
+
+/// Codec work polled by the busyloop alongside the requests.
+#[derive(Default)]
+struct CodecJobs {
+    encodes: FuturesUnordered<BoxFuture<'static, EncodedBlock>>,
+    /// Sequence numbers of the blocks being encoded.
+    encoding: BTreeSet<u64>,
+    next_encode_seq: u64,
+    /// Flushes waiting for the blocks which were being encoded when they
+    /// arrived to be written, along with `next_encode_seq` at that moment.
+    deferred_flushes: Vec<(u64, proto::RequestFlushReplyTx)>,
+    reads: FuturesUnordered<BoxFuture<'static, ()>>,
+}
+
+struct EncodedBlock {
+    encode_seq: u64,
+    encode_result: Result<Bytes, codec::Error>,
+    reply_tx: proto::RequestWriteBlockReplyTx,
+}
+
+/// Writes the block right away unless it has to be encoded first, in which
+/// case the write is issued by the busyloop once the codec job is done.
+fn dispatch_write_block<J>(
+    state: &State<J>,
+    codec_jobs: &mut CodecJobs,
+    blockwheel_fs_meister: &blockwheel_fs::Meister<EchoPolicy>,
+    ftd_sendegeraet: &komm::Sendegeraet<ftd_sklave::Order>,
+    block_bytes: Bytes,
+    reply_tx: proto::RequestWriteBlockReplyTx,
+)
+    -> Result<(), Error>
+where J: From<blockwheel_fs::job::SklaveJob<EchoPolicy>>,
+      J: From<ftd_sklave::SklaveJob>,
+      J: From<codec::CodecJob>,
+      J: Send + 'static,
+{
+    if !codec::needs_encoding(&state.codec_settings, &block_bytes) {
+        return blockwheel_fs_meister
+            .write_block(
+                block_bytes,
+                ftd_sendegeraet.rueckkopplung(reply_tx),
+                &state.thread_pool,
+            )
+            .map_err(Error::RequestWriteBlockBefehl);
+    }
+
+    let thread_pool = state.thread_pool.clone();
+    let blocks_pool = state.blocks_pool.clone();
+    let codec_settings = state.codec_settings.clone();
+    let encode_seq = codec_jobs.next_encode_seq;
+    codec_jobs.next_encode_seq += 1;
+    codec_jobs.encoding.insert(encode_seq);
+    codec_jobs.encodes.push(
+        async move {
+            reply_tx.span().event("encoding block");
+            let encode_result = codec::encode(&thread_pool, &blocks_pool, codec_settings, block_bytes).await;
+            EncodedBlock { encode_seq, encode_result, reply_tx, }
         }
+        .boxed(),
+    );
+    Ok(())
+}
+
+/// Writes a block encoded by [`dispatch_write_block`] and releases the
+/// flushes which were waiting for it.
+fn write_encoded_block<J>(
+    state: &State<J>,
+    codec_jobs: &mut CodecJobs,
+    blockwheel_fs_meister: &blockwheel_fs::Meister<EchoPolicy>,
+    ftd_sendegeraet: &komm::Sendegeraet<ftd_sklave::Order>,
+    encoded_block: EncodedBlock,
+)
+    -> Result<(), Error>
+where J: From<blockwheel_fs::job::SklaveJob<EchoPolicy>>,
+      J: From<ftd_sklave::SklaveJob>,
+      J: Send + 'static,
+{
+    let EncodedBlock { encode_seq, encode_result, reply_tx, } = encoded_block;
+    codec_jobs.encoding.remove(&encode_seq);
+    let block_bytes = encode_result
+        .map_err(Error::EncodeBlock)?;
+    blockwheel_fs_meister
+        .write_block(
+            block_bytes,
+            ftd_sendegeraet.rueckkopplung(reply_tx),
+            &state.thread_pool,
+        )
+        .map_err(Error::RequestWriteBlockBefehl)?;
+
+    let oldest_encoding_seq = codec_jobs.encoding.first()
+        .cloned()
+        .unwrap_or(codec_jobs.next_encode_seq);
+    let mut index = 0;
+    while index < codec_jobs.deferred_flushes.len() {
+        if codec_jobs.deferred_flushes[index].0 > oldest_encoding_seq {
+            index += 1;
+            continue;
+        }
+        let (_flush_seq, reply_tx) = codec_jobs.deferred_flushes.swap_remove(index);
+        reply_tx.span().event("dispatching to blockwheel-fs");
+        blockwheel_fs_meister
+            .flush(
+                ftd_sendegeraet.rueckkopplung(reply_tx),
+                &state.thread_pool,
+            )
+            .map_err(Error::RequestFlushBefehl)?;
     }
+    Ok(())
+}
 
-    log::debug!("request channel is depleted: terminating busyloop");
+/// Reads the block and replies once its frame, if there is one, is
+/// decoded. A block without a frame is replied to as is.
+fn dispatch_read_block<J>(
+    state: &State<J>,
+    codec_jobs: &mut CodecJobs,
+    blockwheel_fs_meister: &blockwheel_fs::Meister<EchoPolicy>,
+    ftd_sendegeraet: &komm::Sendegeraet<ftd_sklave::Order>,
+    block_id: block::Id,
+    reply_tx: proto::RequestReadBlockReplyTx,
+)
+    -> Result<(), Error>
+where J: From<blockwheel_fs::job::SklaveJob<EchoPolicy>>,
+      J: From<ftd_sklave::SklaveJob>,
+      J: From<codec::CodecJob>,
+      J: Send + 'static,
+{
+    let (read_tx, read_rx) = oneshot::channel();
+    blockwheel_fs_meister
+        .read_block(
+            block_id.clone(),
+            ftd_sendegeraet.rueckkopplung(proto::ReplyTx::new(read_tx, reply_tx.span().clone())),
+            &state.thread_pool,
+        )
+        .map_err(Error::RequestReadBlockBefehl)?;
+    let thread_pool = state.thread_pool.clone();
+    let blocks_pool = state.blocks_pool.clone();
+    let codec_settings = state.codec_settings.clone();
+    #[cfg(feature = "fault-injection")]
+    let fault_hook = state.fault_hook.clone();
+    codec_jobs.reads.push(
+        async move {
+            let read_result = match read_rx.await {
+                Ok(read_result) =>
+                    read_result,
+                Err(oneshot::Canceled) =>
+                    // dropping `reply_tx` makes the client resubmit the request
+                    return,
+            };
+            let reply = match read_result {
+                Ok(block_bytes) => {
+                    #[cfg(feature = "fault-injection")]
+                    let block_bytes = corrupt_block(&fault_hook, &blocks_pool, block_bytes);
+                    decode_block(&thread_pool, &blocks_pool, &codec_settings, block_bytes).await
+                        .map_err(|error| read_block_error(&block_id, error))
+                },
+                Err(RequestReadBlockError::NotFound) =>
+                    Err(proto::ReadBlockError::NotFound),
+            };
+            if let Err(_send_error) = reply_tx.send(reply) {
+                log::debug!("client is gone during RequestReadBlock");
+            }
+        }
+        .boxed(),
+    );
     Ok(())
 }
 
+fn read_block_error(block_id: &block::Id, error: codec::Error) -> proto::ReadBlockError {
+    match error {
+        codec::Error::ChecksumMismatch => {
+            log::error!("checksum mismatch for block {:?}", block_id);
+            proto::ReadBlockError::Corrupted
+        },
+        error @ codec::Error::UnknownKey(..) | error @ codec::Error::DecryptionFailed => {
+            log::error!("failed to decrypt block {:?}: {:?}", block_id, error);
+            proto::ReadBlockError::DecryptionFailed
+        },
+        error @ codec::Error::UnknownCodec(..) | error @ codec::Error::Decompress(..) => {
+            log::error!("failed to decompress block {:?}: {:?}", block_id, error);
+            proto::ReadBlockError::Undecodable
+        },
+        error @ codec::Error::Spawn(..) | error @ codec::Error::JobIsLost => {
+            log::error!("block {:?} decoding job failed: {:?}", block_id, error);
+            proto::ReadBlockError::DecodingFailed
+        },
+    }
+}
+
+/// Flips the last byte of the block if a `Fault::CorruptBlock` is armed.
+#[cfg(feature = "fault-injection")]
+fn corrupt_block(fault_hook: &fault::Hook, blocks_pool: &BytesPool, block_bytes: Bytes) -> Bytes {
+    if !fault_hook.corrupt_block() || block_bytes.is_empty() {
+        return block_bytes;
+    }
+    log::debug!("fault injection: corrupting block");
+    let mut corrupted = blocks_pool.lend();
+    corrupted.extend_from_slice(&block_bytes);
+    let last = corrupted.len() - 1;
+    corrupted[last] ^= 0x01;
+    corrupted.freeze()
+}
+
+/// Decodes the block if it carries a frame header.
+async fn decode_block<J>(
+    thread_pool: &edeltraud::Handle<J>,
+    blocks_pool: &BytesPool,
+    codec_settings: &codec::Settings,
+    block_bytes: Bytes,
+)
+    -> Result<Bytes, codec::Error>
+where J: From<codec::CodecJob>,
+{
+    if !codec::needs_decoding(&block_bytes) {
+        return Ok(block_bytes);
+    }
+    codec::decode(thread_pool, blocks_pool, codec_settings.clone(), block_bytes).await
+}
+
+fn spawn_batch_reply<T>(
+    supervisor_pid: &mut SupervisorPid,
+    item_rxs: Vec<oneshot::Receiver<T>>,
+    reply_tx: proto::ReplyTx<Vec<Option<T>>>,
+)
+where T: Send + 'static,
+{
+    supervisor_pid.spawn_link_temporary(async move {
+        let items = future::join_all(
+            item_rxs.into_iter()
+                .map(|item_rx| async move { item_rx.await.ok() }),
+        ).await;
+        if let Err(_send_error) = reply_tx.send(items) {
+            log::debug!("client canceled batch request");
+        }
+    });
+}
+
+/// What a blocks iterator or scrub task takes over from the busyloop state.
+struct IterBlocksEnv<J> {
+    thread_pool: edeltraud::Handle<J>,
+    blocks_pool: BytesPool,
+    codec_settings: codec::Settings,
+    counters: Arc<stats::Counters>,
+    #[cfg(feature = "fault-injection")]
+    fault_hook: fault::Hook,
+    iter_blocks_cancel_rx: IterBlocksCancelRx,
+}
+
 async fn iter_blocks_loop<J>(
     blockwheel_fs_meister: blockwheel_fs::Meister<EchoPolicy>,
     ftd_sendegeraet: komm::Sendegeraet<ftd_sklave::Order>,
+    resume_iterator_next: Option<blockwheel_fs::IterBlocksIterator>,
     reply_tx: proto::RequestIterBlocksReplyTx,
-    thread_pool: &edeltraud::Handle<J>,
+    env: &IterBlocksEnv<J>,
 )
     -> Result<(), Error>
 where J: From<blockwheel_fs::job::SklaveJob<EchoPolicy>>,
       J: From<ftd_sklave::SklaveJob>,
+      J: From<codec::CodecJob>,
       J: Send + 'static,
 {
-    let (iter_blocks_init_tx, iter_blocks_init_rx) = oneshot::channel();
-    blockwheel_fs_meister
-        .iter_blocks_init(
-            ftd_sendegeraet.rueckkopplung(ftd_sklave::RequestIterBlocksInit {
-                iter_blocks_init_tx,
-            }),
-            thread_pool,
-        )
-        .map_err(Error::RequestIterBlocksInitBefehl)?;
-    let iter_blocks = iter_blocks_init_rx.await
-        .map_err(|oneshot::Canceled| Error::FtdSklaveIsGoneDuringIterBlocksInit)?;
+    let IterBlocksEnv { thread_pool, blocks_pool, codec_settings, counters, iter_blocks_cancel_rx, .. } = env;
+    let iter_blocks = iter_blocks_init(&blockwheel_fs_meister, &ftd_sendegeraet, thread_pool).await?;
 
     let (mut blocks_tx, blocks_rx) = mpsc::channel(0);
     let iter_blocks_reply = IterBlocks {
@@ -252,15 +934,29 @@ where J: From<blockwheel_fs::job::SklaveJob<EchoPolicy>>,
         return Ok(());
     }
 
-    let mut current_iterator_next = iter_blocks.iterator_next;
+    // the totals are taken from the init reply even when resuming, the
+    // iteration itself continues from the cursor position
+    let mut current_iterator_next = resume_iterator_next
+        .unwrap_or(iter_blocks.iterator_next);
     loop {
+        #[cfg(feature = "fault-injection")]
+        if env.fault_hook.fail_spawn() {
+            return Err(Error::FaultInjectedOnThreadPoolSpawn);
+        }
+        #[cfg(feature = "fault-injection")]
+        if env.fault_hook.cancel_reply() {
+            log::debug!("fault injection: dropping blocks iterator item");
+            return Err(Error::FtdSklaveIsGoneDuringIterBlocksNext);
+        }
         let (iter_blocks_next_tx, iter_blocks_next_rx) = oneshot::channel();
         blockwheel_fs_meister
             .iter_blocks_next(
                 current_iterator_next,
-                ftd_sendegeraet.rueckkopplung(ftd_sklave::RequestIterBlocksNext {
-                    iter_blocks_next_tx,
-                }),
+                ftd_sklave::IterBlocksNextEcho::Blocks(
+                    ftd_sendegeraet.rueckkopplung(ftd_sklave::RequestIterBlocksNext {
+                        iter_blocks_next_tx,
+                    }),
+                ),
                 thread_pool,
             )
             .map_err(Error::RequestIterBlocksNextBefehl)?;
@@ -268,15 +964,33 @@ where J: From<blockwheel_fs::job::SklaveJob<EchoPolicy>>,
             .map_err(|oneshot::Canceled| Error::FtdSklaveIsGoneDuringIterBlocksNext)?;
         match iter_blocks_item {
             blockwheel_fs::IterBlocksItem::Block { block_id, block_bytes, iterator_next, } => {
-                let item = IterBlocksItem::Block { block_id, block_bytes, };
-                if let Err(_send_error) = blocks_tx.send(item).await {
+                #[cfg(feature = "fault-injection")]
+                let block_bytes = corrupt_block(&env.fault_hook, blocks_pool, block_bytes);
+                let cursor = IterBlocksCursor::new(iterator_next.clone());
+                let item = match decode_block(thread_pool, blocks_pool, codec_settings, block_bytes).await {
+                    Ok(block_bytes) => {
+                        counters.bytes_read.fetch_add(block_bytes.len() as u64, Ordering::Relaxed);
+                        IterBlocksItem::Block { block_id, block_bytes, cursor, }
+                    },
+                    Err(error @ codec::Error::ChecksumMismatch) |
+                    Err(error @ codec::Error::UnknownKey(..)) |
+                    Err(error @ codec::Error::DecryptionFailed) |
+                    Err(error @ codec::Error::UnknownCodec(..)) |
+                    Err(error @ codec::Error::Decompress(..)) => {
+                        log::error!("failed to decode block {:?}: {:?}", block_id, error);
+                        IterBlocksItem::Corrupted { block_id, cursor, }
+                    },
+                    Err(error) =>
+                        return Err(Error::DecodeBlock(error)),
+                };
+                if !send_iter_item(&mut blocks_tx, item, iter_blocks_cancel_rx).await {
                     log::debug!("client canceled iter IterBlocks request (stream)");
                     return Ok(());
                 }
                 current_iterator_next = iterator_next;
             },
             blockwheel_fs::IterBlocksItem::NoMoreBlocks => {
-                if let Err(_send_error) = blocks_tx.send(IterBlocksItem::NoMoreBlocks).await {
+                if !send_iter_item(&mut blocks_tx, IterBlocksItem::NoMoreBlocks, iter_blocks_cancel_rx).await {
                     log::debug!("client canceled iter IterBlocks request (stream)");
                 }
                 return Ok(());
@@ -284,3 +998,184 @@ where J: From<blockwheel_fs::job::SklaveJob<EchoPolicy>>,
         }
     }
 }
+
+/// Verifies every block of the wheel. The pass is abandoned without a reply
+/// when the server shuts down and closes the active iterators.
+async fn scrub_loop<J>(
+    blockwheel_fs_meister: blockwheel_fs::Meister<EchoPolicy>,
+    ftd_sendegeraet: komm::Sendegeraet<ftd_sklave::Order>,
+    reply_tx: proto::RequestScrubReplyTx,
+    env: &IterBlocksEnv<J>,
+)
+    -> Result<(), Error>
+where J: From<blockwheel_fs::job::SklaveJob<EchoPolicy>>,
+      J: From<ftd_sklave::SklaveJob>,
+      J: From<codec::CodecJob>,
+      J: Send + 'static,
+{
+    let IterBlocksEnv { thread_pool, blocks_pool, codec_settings, iter_blocks_cancel_rx, .. } = env;
+    let iter_blocks = iter_blocks_init(&blockwheel_fs_meister, &ftd_sendegeraet, thread_pool).await?;
+
+    let mut scrub_report = ScrubReport::default();
+    let mut current_iterator_next = iter_blocks.iterator_next;
+    loop {
+        let (iter_blocks_next_tx, iter_blocks_next_rx) = oneshot::channel();
+        blockwheel_fs_meister
+            .iter_blocks_next(
+                current_iterator_next,
+                ftd_sklave::IterBlocksNextEcho::Blocks(
+                    ftd_sendegeraet.rueckkopplung(ftd_sklave::RequestIterBlocksNext {
+                        iter_blocks_next_tx,
+                    }),
+                ),
+                thread_pool,
+            )
+            .map_err(Error::RequestIterBlocksNextBefehl)?;
+        let iter_blocks_item = select! {
+            next_result = iter_blocks_next_rx.fuse() =>
+                next_result.map_err(|oneshot::Canceled| Error::FtdSklaveIsGoneDuringIterBlocksNext)?,
+            _ = iter_blocks_cancel_rx.clone() => {
+                log::debug!("server is shutting down: abandoning scrub");
+                return Ok(());
+            },
+        };
+        match iter_blocks_item {
+            blockwheel_fs::IterBlocksItem::Block { block_id, block_bytes, iterator_next, } => {
+                #[cfg(feature = "fault-injection")]
+                let block_bytes = corrupt_block(&env.fault_hook, blocks_pool, block_bytes);
+                scrub_report.blocks_checked += 1;
+                if !codec::has_checksum(&block_bytes) {
+                    scrub_report.blocks_without_checksum += 1;
+                }
+                match decode_block(thread_pool, blocks_pool, codec_settings, block_bytes).await {
+                    Ok(..) =>
+                        (),
+                    Err(error @ codec::Error::ChecksumMismatch) |
+                    Err(error @ codec::Error::DecryptionFailed) |
+                    Err(error @ codec::Error::UnknownCodec(..)) |
+                    Err(error @ codec::Error::Decompress(..)) => {
+                        log::error!("scrub: block {:?} is corrupted: {:?}", block_id, error);
+                        scrub_report.corrupted_block_ids.push(block_id);
+                    },
+                    Err(codec::Error::UnknownKey(key_id)) =>
+                        log::warn!("scrub: no key with id {} to verify block {:?}", key_id, block_id),
+                    Err(error) =>
+                        return Err(Error::DecodeBlock(error)),
+                }
+                current_iterator_next = iterator_next;
+            },
+            blockwheel_fs::IterBlocksItem::NoMoreBlocks => {
+                if let Err(_send_error) = reply_tx.send(scrub_report) {
+                    log::debug!("client canceled Scrub request");
+                }
+                return Ok(());
+            },
+        }
+    }
+}
+
+async fn iter_block_ids_loop<J>(
+    blockwheel_fs_meister: blockwheel_fs::Meister<EchoPolicy>,
+    ftd_sendegeraet: komm::Sendegeraet<ftd_sklave::Order>,
+    reply_tx: proto::RequestIterBlockIdsReplyTx,
+    thread_pool: &edeltraud::Handle<J>,
+    iter_blocks_cancel_rx: IterBlocksCancelRx,
+)
+    -> Result<(), Error>
+where J: From<blockwheel_fs::job::SklaveJob<EchoPolicy>>,
+      J: From<ftd_sklave::SklaveJob>,
+      J: Send + 'static,
+{
+    let iter_blocks = iter_blocks_init(&blockwheel_fs_meister, &ftd_sendegeraet, thread_pool).await?;
+
+    let (mut block_ids_tx, block_ids_rx) = mpsc::channel(0);
+    let iter_block_ids_reply = IterBlockIds {
+        blocks_total_count: iter_blocks.blocks_total_count,
+        blocks_total_size: iter_blocks.blocks_total_size,
+        block_ids_rx,
+    };
+    if let Err(_send_error) = reply_tx.send(iter_block_ids_reply) {
+        log::debug!("client canceled iter IterBlockIds request (init)");
+        return Ok(());
+    }
+
+    let mut current_iterator_next = iter_blocks.iterator_next;
+    loop {
+        let (iter_block_ids_next_tx, iter_block_ids_next_rx) = oneshot::channel();
+        blockwheel_fs_meister
+            .iter_blocks_next(
+                current_iterator_next,
+                ftd_sklave::IterBlocksNextEcho::BlockIds(
+                    ftd_sendegeraet.rueckkopplung(ftd_sklave::RequestIterBlockIdsNext {
+                        iter_block_ids_next_tx,
+                    }),
+                ),
+                thread_pool,
+            )
+            .map_err(Error::RequestIterBlocksNextBefehl)?;
+        let iter_block_ids_item = iter_block_ids_next_rx.await
+            .map_err(|oneshot::Canceled| Error::FtdSklaveIsGoneDuringIterBlocksNext)?;
+        match iter_block_ids_item {
+            ftd_sklave::IterBlockIdsNextItem::BlockId { block_id, block_size, iterator_next, } => {
+                let item = IterBlockIdsItem::BlockId { block_id, block_size, };
+                if !send_iter_item(&mut block_ids_tx, item, &iter_blocks_cancel_rx).await {
+                    log::debug!("client canceled iter IterBlockIds request (stream)");
+                    return Ok(());
+                }
+                current_iterator_next = iterator_next;
+            },
+            ftd_sklave::IterBlockIdsNextItem::NoMoreBlocks => {
+                if !send_iter_item(&mut block_ids_tx, IterBlockIdsItem::NoMoreBlocks, &iter_blocks_cancel_rx).await {
+                    log::debug!("client canceled iter IterBlockIds request (stream)");
+                }
+                return Ok(());
+            },
+        }
+    }
+}
+
+/// Resolves once the server shuts down and closes the active iterators.
+type IterBlocksCancelRx = future::Shared<oneshot::Receiver<()>>;
+
+/// Sends an iterator item to the client unless the server starts shutting
+/// down before the client takes it. Returns `false` if the item was not
+/// delivered.
+async fn send_iter_item<T>(
+    items_tx: &mut mpsc::Sender<T>,
+    item: T,
+    iter_blocks_cancel_rx: &IterBlocksCancelRx,
+)
+    -> bool
+{
+    select! {
+        send_result = items_tx.send(item).fuse() =>
+            send_result.is_ok(),
+        _ = iter_blocks_cancel_rx.clone() => {
+            log::debug!("server is shutting down: closing blocks iterator");
+            false
+        },
+    }
+}
+
+async fn iter_blocks_init<J>(
+    blockwheel_fs_meister: &blockwheel_fs::Meister<EchoPolicy>,
+    ftd_sendegeraet: &komm::Sendegeraet<ftd_sklave::Order>,
+    thread_pool: &edeltraud::Handle<J>,
+)
+    -> Result<blockwheel_fs::IterBlocks, Error>
+where J: From<blockwheel_fs::job::SklaveJob<EchoPolicy>>,
+      J: From<ftd_sklave::SklaveJob>,
+      J: Send + 'static,
+{
+    let (iter_blocks_init_tx, iter_blocks_init_rx) = oneshot::channel();
+    blockwheel_fs_meister
+        .iter_blocks_init(
+            ftd_sendegeraet.rueckkopplung(ftd_sklave::RequestIterBlocksInit {
+                iter_blocks_init_tx,
+            }),
+            thread_pool,
+        )
+        .map_err(Error::RequestIterBlocksInitBefehl)?;
+    iter_blocks_init_rx.await
+        .map_err(|oneshot::Canceled| Error::FtdSklaveIsGoneDuringIterBlocksInit)
+}
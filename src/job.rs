@@ -1,13 +1,21 @@
+use std::{
+    cell::{
+        Cell,
+    },
+};
+
 use crate::{
     echo_policy::{
         EchoPolicy,
     },
     ftd_sklave,
+    codec,
 };
 
 pub enum Job {
     BlockwheelFs(blockwheel_fs::job::Job<EchoPolicy>),
     FtdSklave(ftd_sklave::SklaveJob),
+    Codec(codec::CodecJob),
 }
 
 impl From<blockwheel_fs::job::Job<EchoPolicy>> for Job {
@@ -22,12 +30,53 @@ impl From<blockwheel_fs::job::SklaveJob<EchoPolicy>> for Job {
     }
 }
 
+// blockwheel-fs spawns these jobs through the handle it is given, and
+// `JobUnit` runs them with `J = Job`, so `Job` has to be convertible from
+// each of them for the `edeltraud::Job` bounds below to hold.
+impl From<blockwheel_fs::job::BlockPrepareWriteJob<EchoPolicy>> for Job {
+    fn from(job: blockwheel_fs::job::BlockPrepareWriteJob<EchoPolicy>) -> Self {
+        Self::BlockwheelFs(job.into())
+    }
+}
+
+impl From<blockwheel_fs::job::BlockPrepareDeleteJob<EchoPolicy>> for Job {
+    fn from(job: blockwheel_fs::job::BlockPrepareDeleteJob<EchoPolicy>) -> Self {
+        Self::BlockwheelFs(job.into())
+    }
+}
+
+impl From<blockwheel_fs::job::BlockProcessReadJob<EchoPolicy>> for Job {
+    fn from(job: blockwheel_fs::job::BlockProcessReadJob<EchoPolicy>) -> Self {
+        Self::BlockwheelFs(job.into())
+    }
+}
+
 impl From<ftd_sklave::SklaveJob> for Job {
     fn from(job: ftd_sklave::SklaveJob) -> Self {
         Self::FtdSklave(job)
     }
 }
 
+impl From<codec::CodecJob> for Job {
+    fn from(job: codec::CodecJob) -> Self {
+        Self::Codec(job)
+    }
+}
+
+thread_local! {
+    /// Set on a thread once it runs a job of this crate, which makes it a
+    /// worker of a thread pool serving a gen server.
+    static THREAD_POOL_WORKER: Cell<bool> = const { Cell::new(false) };
+}
+
+pub(crate) fn mark_thread_pool_worker() {
+    THREAD_POOL_WORKER.with(|worker| worker.set(true));
+}
+
+pub(crate) fn is_thread_pool_worker() -> bool {
+    THREAD_POOL_WORKER.with(Cell::get)
+}
+
 pub struct JobUnit<J>(edeltraud::JobUnit<J, Job>);
 
 impl<J> From<edeltraud::JobUnit<J, Job>> for JobUnit<J> {
@@ -43,6 +92,7 @@ where J: From<blockwheel_fs::job::SklaveJob<EchoPolicy>>,
       J: From<blockwheel_fs::job::BlockProcessReadJob<EchoPolicy>>,
 {
     fn run(self) {
+        mark_thread_pool_worker();
         match self.0.job {
             Job::BlockwheelFs(job) => {
                 let job_unit = blockwheel_fs::job::JobUnit::from(edeltraud::JobUnit {
@@ -54,6 +104,9 @@ where J: From<blockwheel_fs::job::SklaveJob<EchoPolicy>>,
             Job::FtdSklave(job) => {
                 ftd_sklave::job(job, &self.0.handle);
             },
+            Job::Codec(job) => {
+                codec::job(job);
+            },
         }
     }
 }
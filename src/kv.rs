@@ -0,0 +1,303 @@
+use std::{
+    sync::{
+        atomic::{
+            Ordering,
+            AtomicU64,
+        },
+        Arc,
+        Mutex,
+    },
+    collections::{
+        BTreeMap,
+    },
+};
+
+use futures::{
+    StreamExt,
+};
+
+use alloc_pool::{
+    bytes::{
+        Bytes,
+        BytesPool,
+    },
+};
+
+use crate::{
+    block,
+    Pid,
+    Deleted,
+    PutError,
+    ReadBlockError,
+    IterBlocksError,
+    DeleteBlockError,
+};
+
+/// Prefix of every block written by [`KvPid`], followed by the record
+/// version, the record sequence number (u64 LE), the key length (u32 LE)
+/// and the crc32c (u32 LE) of everything else in the block: the header
+/// fields before it, the key and the value.
+const RECORD_MAGIC: &[u8; 4] = b"bwkv";
+const RECORD_VERSION: u8 = 1;
+const RECORD_HEADER_SIZE: usize = RECORD_MAGIC.len() + 1 + 8 + 4 + 4;
+
+/// Key-value layer over a `Pid`.
+///
+/// Each value is stored in its own block prefixed with its key, so the
+/// wheel itself is the durable index: [`KvPid::open`] rebuilds the in-memory
+/// key to block id map by iterating over all blocks. Blocks without a valid
+/// record header and checksum are left alone.
+///
+/// Every record carries a sequence number, which tells which of two records
+/// for the same key was written last.
+#[derive(Clone)]
+pub struct KvPid {
+    pid: Pid,
+    blocks_pool: BytesPool,
+    index: Arc<Mutex<BTreeMap<Vec<u8>, Entry>>>,
+    next_seq: Arc<AtomicU64>,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    block_id: block::Id,
+    seq: u64,
+}
+
+impl KvPid {
+    /// Rebuilds the index from the wheel contents.
+    ///
+    /// If a key is found in several blocks, which happens when a `put` was
+    /// interrupted before deleting the previous value, the record with the
+    /// highest sequence number wins and the stale ones are deleted. Only
+    /// blocks that pass the record checksum are ever deleted.
+    pub async fn open(mut pid: Pid, blocks_pool: BytesPool) -> Result<KvPid, IterBlocksError> {
+        let mut index: BTreeMap<Vec<u8>, Entry> = BTreeMap::new();
+        let mut stale_block_ids = Vec::new();
+        let mut next_seq = 0;
+
+        let mut blocks = pid.iter_blocks().await?.into_stream();
+        while let Some(item) = blocks.next().await {
+            let (block_id, block_bytes) = match item {
+                Ok(block) =>
+                    block,
+                Err(IterBlocksError::Corrupted { block_id, }) => {
+                    log::warn!("skipping corrupted block {:?} while opening kv", block_id);
+                    continue;
+                },
+                Err(error) =>
+                    return Err(error),
+            };
+            let (seq, key) = match decode_record(&block_bytes) {
+                None =>
+                    continue,
+                Some((seq, key, _value)) =>
+                    (seq, key.to_vec()),
+            };
+            next_seq = next_seq.max(seq.saturating_add(1));
+            let entry = Entry { block_id, seq, };
+            match index.get_mut(&key) {
+                None => {
+                    index.insert(key, entry);
+                },
+                Some(current) if current.seq < entry.seq => {
+                    let stale = std::mem::replace(current, entry);
+                    stale_block_ids.push(stale.block_id);
+                },
+                Some(current) if current.seq > entry.seq =>
+                    stale_block_ids.push(entry.block_id),
+                Some(current) =>
+                    log::warn!(
+                        "kv blocks {:?} and {:?} hold records with the same sequence number, keeping both",
+                        current.block_id,
+                        entry.block_id,
+                    ),
+            }
+        }
+
+        for block_id in stale_block_ids {
+            match pid.delete_block(block_id.clone()).await {
+                Ok(Deleted) | Err(DeleteBlockError::NotFound) =>
+                    (),
+                Err(error) =>
+                    log::warn!("failed to delete stale kv block {:?}: {:?}", block_id, error),
+            }
+        }
+
+        Ok(KvPid {
+            pid,
+            blocks_pool,
+            index: Arc::new(Mutex::new(index)),
+            next_seq: Arc::new(AtomicU64::new(next_seq)),
+        })
+    }
+
+    /// Stores `value` under `key`, deleting the block holding the previous
+    /// value once the new one is written.
+    pub async fn put(&mut self, key: &[u8], value: Bytes) -> Result<(), PutError> {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        let mut block_bytes = self.blocks_pool.lend();
+        encode_record(&mut block_bytes, seq, key, &value)?;
+
+        let block_id = self.pid.write_block(block_bytes.freeze()).await
+            .map_err(PutError::WriteBlock)?;
+        let entry = Entry { block_id, seq, };
+        // a concurrent `put` of the same key may have started later but
+        // finished first, then the block just written is the stale one
+        let maybe_stale_block_id = {
+            let mut index = self.index.lock().unwrap();
+            match index.get_mut(key) {
+                Some(current) if current.seq > entry.seq =>
+                    Some(entry.block_id),
+                Some(current) =>
+                    Some(std::mem::replace(current, entry).block_id),
+                None => {
+                    index.insert(key.to_vec(), entry);
+                    None
+                },
+            }
+        };
+        if let Some(prev_block_id) = maybe_stale_block_id {
+            match self.pid.delete_block(prev_block_id.clone()).await {
+                Ok(Deleted) | Err(DeleteBlockError::NotFound) =>
+                    (),
+                Err(error) =>
+                    log::warn!("failed to delete previous kv block {:?}: {:?}", prev_block_id, error),
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn get(&mut self, key: &[u8]) -> Result<Bytes, ReadBlockError> {
+        let block_id = self.index.lock().unwrap()
+            .get(key)
+            .map(|entry| entry.block_id.clone())
+            .ok_or(ReadBlockError::NotFound)?;
+        let block_bytes = self.pid.read_block(block_id.clone()).await?;
+        match decode_record(&block_bytes) {
+            Some((_seq, record_key, value)) if record_key == key => {
+                let mut value_bytes = self.blocks_pool.lend();
+                value_bytes.extend_from_slice(value);
+                Ok(value_bytes.freeze())
+            },
+            _ => {
+                log::error!("block {:?} does not hold a kv record for the requested key", block_id);
+                Err(ReadBlockError::NotFound)
+            },
+        }
+    }
+
+    /// Deletes the block holding the value of `key`. The key is dropped from
+    /// the index only once the block is gone, so a failed delete leaves the
+    /// key in place.
+    pub async fn remove(&mut self, key: &[u8]) -> Result<Deleted, DeleteBlockError> {
+        let block_id = self.index.lock().unwrap()
+            .get(key)
+            .map(|entry| entry.block_id.clone())
+            .ok_or(DeleteBlockError::NotFound)?;
+        let result = self.pid.delete_block(block_id.clone()).await;
+        if let Ok(Deleted) | Err(DeleteBlockError::NotFound) = result {
+            let mut index = self.index.lock().unwrap();
+            // the key may have been put again meanwhile
+            if index.get(key).is_some_and(|entry| entry.block_id == block_id) {
+                index.remove(key);
+            }
+        }
+        result
+    }
+
+    /// Currently stored keys in ascending order.
+    pub fn keys(&self) -> Vec<Vec<u8>> {
+        self.index.lock().unwrap()
+            .keys()
+            .cloned()
+            .collect()
+    }
+}
+
+fn encode_record(block_bytes: &mut Vec<u8>, seq: u64, key: &[u8], value: &[u8]) -> Result<(), PutError> {
+    let key_len = u32::try_from(key.len())
+        .map_err(|_| PutError::KeyTooLong)?;
+    block_bytes.reserve(RECORD_HEADER_SIZE + key.len() + value.len());
+    block_bytes.extend_from_slice(RECORD_MAGIC);
+    block_bytes.push(RECORD_VERSION);
+    block_bytes.extend_from_slice(&seq.to_le_bytes());
+    block_bytes.extend_from_slice(&key_len.to_le_bytes());
+    let checksum = record_checksum(block_bytes, key, value);
+    block_bytes.extend_from_slice(&checksum.to_le_bytes());
+    block_bytes.extend_from_slice(key);
+    block_bytes.extend_from_slice(value);
+    Ok(())
+}
+
+fn decode_record(block_bytes: &[u8]) -> Option<(u64, &[u8], &[u8])> {
+    if block_bytes.len() < RECORD_HEADER_SIZE {
+        return None;
+    }
+    let (header, body) = block_bytes.split_at(RECORD_HEADER_SIZE);
+    let (prefix, checksum_bytes) = header.split_at(RECORD_HEADER_SIZE - 4);
+    if !prefix.starts_with(&RECORD_MAGIC[..]) || prefix[RECORD_MAGIC.len()] != RECORD_VERSION {
+        return None;
+    }
+    let (seq_bytes, key_len_bytes) = prefix[RECORD_MAGIC.len() + 1 ..].split_at(8);
+    let seq = u64::from_le_bytes(seq_bytes.try_into().ok()?);
+    let key_len = u32::from_le_bytes(key_len_bytes.try_into().ok()?) as usize;
+    if body.len() < key_len {
+        return None;
+    }
+    let (key, value) = body.split_at(key_len);
+    let checksum = u32::from_le_bytes(checksum_bytes.try_into().ok()?);
+    if record_checksum(prefix, key, value) != checksum {
+        return None;
+    }
+    Some((seq, key, value))
+}
+
+fn record_checksum(prefix: &[u8], key: &[u8], value: &[u8]) -> u32 {
+    let checksum = crc32c::crc32c(prefix);
+    let checksum = crc32c::crc32c_append(checksum, key);
+    crc32c::crc32c_append(checksum, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        encode_record,
+        decode_record,
+        RECORD_MAGIC,
+    };
+
+    fn record(seq: u64, key: &[u8], value: &[u8]) -> Vec<u8> {
+        let mut block_bytes = Vec::new();
+        encode_record(&mut block_bytes, seq, key, value).unwrap();
+        block_bytes
+    }
+
+    #[test]
+    fn record_round_trip() {
+        for (key, value) in [(&b""[..], &b""[..]), (b"key", b""), (b"", b"value"), (b"key", b"value")] {
+            for seq in [0, 1, u64::MAX] {
+                assert_eq!(decode_record(&record(seq, key, value)), Some((seq, key, value)));
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_record_is_rejected() {
+        let block_bytes = record(7, b"key", b"value");
+        for offset in RECORD_MAGIC.len() .. block_bytes.len() {
+            let mut corrupted = block_bytes.clone();
+            corrupted[offset] ^= 0x01;
+            assert_eq!(decode_record(&corrupted), None, "flipped byte at offset {}", offset);
+        }
+        assert_eq!(decode_record(&block_bytes[.. block_bytes.len() - 1]), None);
+    }
+
+    #[test]
+    fn foreign_block_with_magic_is_rejected() {
+        let mut block_bytes = RECORD_MAGIC.to_vec();
+        block_bytes.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0]);
+        block_bytes.extend_from_slice(b"keyvalue");
+        assert_eq!(decode_record(&block_bytes), None);
+    }
+}
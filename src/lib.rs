@@ -1,15 +1,55 @@
 #![forbid(unsafe_code)]
 
+use std::{
+    pin::{
+        Pin,
+    },
+    sync::{
+        atomic::{
+            Ordering,
+        },
+        Arc,
+    },
+    task::{
+        Poll,
+        Context,
+    },
+    time::{
+        Instant,
+        Duration,
+    },
+};
+
 use futures::{
     channel::{
         mpsc,
         oneshot,
     },
-    stream,
+    future,
+    stream::{
+        self,
+        FusedStream,
+    },
+    lock::{
+        Mutex,
+    },
+    Future,
+    Stream,
     SinkExt,
     StreamExt,
 };
 
+use tokio::{
+    sync::{
+        watch,
+    },
+};
+
+use serde::{
+    Serialize,
+    Deserialize,
+};
+
 use alloc_pool::{
     bytes::{
         Bytes,
@@ -32,20 +72,122 @@ pub use blockwheel_fs::{
 };
 
 pub mod job;
+pub mod stats;
 
 mod proto;
+mod codec;
+#[cfg(feature = "encryption")]
+mod encryption;
+mod trace;
+mod fault;
 mod gen_server;
 mod ftd_sklave;
 mod echo_policy;
+mod restart_policy;
+mod write_tokens;
+mod error;
+mod sharded;
+mod mirrored;
+mod kv;
+mod object;
+mod blocking;
+mod block_store;
+
+pub use error::{
+    Error,
+    InfoError,
+    FlushError,
+    ReadBlockError,
+    WriteBlockError,
+    ResyncError,
+    PutError,
+    ShardingError,
+    IterBlocksError,
+    ReadObjectError,
+    WriteObjectError,
+    DeleteBlockError,
+    DeleteObjectError,
+    StatsError,
+    ShutdownError,
+};
+
+pub use sharded::{
+    ShardedPid,
+    ShardedBlockId,
+    ShardingStrategy,
+    ShardedIterBlocks,
+};
+
+pub use mirrored::{
+    MirroredPid,
+    ResyncReport,
+};
+
+pub use kv::KvPid;
+
+pub use blocking::{
+    BlockingPid,
+    BlockingIterBlocks,
+};
+
+pub use block_store::{
+    BlockStore,
+    FakeCursor,
+    FakeBlockStore,
+};
+
+pub use codec::Compression;
+
+#[cfg(feature = "encryption")]
+pub use encryption::EncryptionKeys;
+
+#[cfg(feature = "fault-injection")]
+pub use fault::{
+    Fault,
+    FaultInjector,
+};
+
+pub use restart_policy::{
+    Backoff,
+    RestartLimit,
+    RestartPolicy,
+};
 
 pub struct GenServer {
     request_tx: mpsc::Sender<proto::Request>,
     fused_request_rx: stream::Fuse<mpsc::Receiver<proto::Request>>,
+    codec_settings: codec::Settings,
+    restart_policy: RestartPolicy,
+    counters: Arc<stats::Counters>,
+    health_tx: watch::Sender<Health>,
+    #[cfg(feature = "fault-injection")]
+    fault_hook: fault::Hook,
 }
 
 #[derive(Clone)]
 pub struct Pid {
     request_tx: mpsc::Sender<proto::Request>,
+    // shared by requests resubmitted from `submit_*` replies, so that they
+    // do not get a guaranteed channel slot each
+    resubmit_tx: Arc<Mutex<mpsc::Sender<proto::Request>>>,
+    timeout: Option<Duration>,
+    counters: Arc<stats::Counters>,
+    health_rx: watch::Receiver<Health>,
+}
+
+pub use gen_server::Error as GenServerError;
+
+/// Lifecycle state of the gen server as observed through [`Pid::health`].
+#[derive(Clone, Debug)]
+pub enum Health {
+    Starting,
+    Running,
+    /// The server has failed with `reason` and is about to reopen the wheel.
+    Restarting { reason: Arc<GenServerError>, },
+    /// The server has failed with `reason` and will not be restarted.
+    Failed { reason: Arc<GenServerError>, },
+    /// The server has stopped after a shutdown or after all `Pid`s are gone.
+    Terminated,
 }
 
 impl Default for GenServer {
@@ -56,16 +198,84 @@ impl Default for GenServer {
 
 impl GenServer {
     pub fn new() -> GenServer {
-        let (request_tx, request_rx) = mpsc::channel(0);
+        Self::with_capacity(0)
+    }
+
+    /// Creates a server whose request channel buffers up to `capacity`
+    /// requests in addition to the slot guaranteed to every `Pid` clone.
+    pub fn with_capacity(capacity: usize) -> GenServer {
+        let (request_tx, request_rx) = mpsc::channel(capacity);
+        let (health_tx, _health_rx) = watch::channel(Health::Starting);
         GenServer {
             request_tx,
             fused_request_rx: request_rx.fuse(),
+            codec_settings: codec::Settings {
+                checksums: true,
+                ..Default::default()
+            },
+            restart_policy: RestartPolicy::default(),
+            counters: Arc::new(stats::Counters::default()),
+            health_tx,
+            #[cfg(feature = "fault-injection")]
+            fault_hook: fault::Hook::default(),
         }
     }
 
+    /// Sets what the server does when its blockwheel-fs meister fails,
+    /// [`RestartPolicy::InstantCrash`] by default.
+    pub fn with_restart_policy(mut self, restart_policy: RestartPolicy) -> GenServer {
+        self.restart_policy = restart_policy;
+        self
+    }
+
+    /// Compresses newly written blocks with `compression`. Blocks are
+    /// decompressed on read regardless of this setting as long as their
+    /// codec is compiled in, otherwise the read fails with
+    /// [`ReadBlockError::Undecodable`].
+    pub fn with_compression(mut self, compression: Compression) -> GenServer {
+        self.codec_settings.compression = compression;
+        self
+    }
+
+    /// Adds a crc32c checksum to newly written blocks, which is the
+    /// default. Checksums are verified on read regardless of this setting, a
+    /// mismatch is reported as [`ReadBlockError::Corrupted`].
+    pub fn with_checksums(mut self) -> GenServer {
+        self.codec_settings.checksums = true;
+        self
+    }
+
+    /// Writes blocks without a checksum. Blocks which need no other encoding
+    /// then skip the codec job on both write and read.
+    pub fn without_checksums(mut self) -> GenServer {
+        self.codec_settings.checksums = false;
+        self
+    }
+
+    /// Encrypts newly written blocks with the active key of `keys` and
+    /// decrypts blocks sealed with any of them on read. A block which fails
+    /// to decrypt is reported as [`ReadBlockError::DecryptionFailed`].
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption(mut self, keys: EncryptionKeys) -> GenServer {
+        self.codec_settings.encryption = Some(Arc::new(keys));
+        self
+    }
+
+    /// Lets a test make the server fail on purpose, faults are armed through
+    /// any clone of `fault_injector`.
+    #[cfg(feature = "fault-injection")]
+    pub fn with_fault_injector(mut self, fault_injector: FaultInjector) -> GenServer {
+        self.fault_hook = fault::Hook::new(fault_injector);
+        self
+    }
+
     pub fn pid(&self) -> Pid {
         Pid {
             request_tx: self.request_tx.clone(),
+            resubmit_tx: Arc::new(Mutex::new(self.request_tx.clone())),
+            timeout: None,
+            counters: self.counters.clone(),
+            health_rx: self.health_tx.subscribe(),
         }
     }
 
@@ -78,165 +288,945 @@ impl GenServer {
     )
     where J: From<blockwheel_fs::job::SklaveJob<echo_policy::EchoPolicy>>,
           J: From<ftd_sklave::SklaveJob>,
+          J: From<codec::CodecJob>,
           J: Send + 'static,
     {
+        let GenServer { request_tx, fused_request_rx, codec_settings, restart_policy, counters, health_tx, #[cfg(feature = "fault-injection")] fault_hook, } = self;
+        // the server should stop once every `Pid` is gone, so its own sender
+        // must not outlive this point
+        drop(request_tx);
+        let config = gen_server::GenServerConfig {
+            restart_policy,
+            codec_settings,
+            counters,
+            health_tx,
+            #[cfg(feature = "fault-injection")]
+            fault_hook,
+        };
         gen_server::run(
-            self.fused_request_rx,
+            fused_request_rx,
             parent_supervisor,
             params,
             blocks_pool,
             thread_pool,
+            config,
         ).await
     }
 }
 
-#[derive(Debug)]
-pub enum WriteBlockError {
-    GenServer(ero::NoProcError),
-    NoSpaceLeft,
+/// Client supplied identity of a write, see [`Pid::write_block_with_token`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct WriteToken(pub u64);
+
+/// Acknowledgement of a completed [`Pid::shutdown`]: every accepted request
+/// has been served and the wheel has been flushed.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Terminated;
+
+/// Result of a [`Pid::scrub`] pass over the whole wheel.
+#[derive(Clone, Debug, Default)]
+pub struct ScrubReport {
+    pub blocks_checked: usize,
+    /// Blocks written without a checksum, which could not be verified.
+    pub blocks_without_checksum: usize,
+    pub corrupted_block_ids: Vec<block::Id>,
 }
 
+/// Blocks iteration reply, `C` is the cursor type of the [`BlockStore`] it
+/// comes from.
 #[derive(Debug)]
-pub enum ReadBlockError {
-    GenServer(ero::NoProcError),
-    NotFound,
+pub struct IterBlocks<C = IterBlocksCursor> {
+    pub blocks_total_count: usize,
+    pub blocks_total_size: usize,
+    pub blocks_rx: mpsc::Receiver<IterBlocksItem<C>>,
 }
 
 #[derive(Debug)]
-pub enum DeleteBlockError {
-    GenServer(ero::NoProcError),
-    NotFound,
+pub enum IterBlocksItem<C = IterBlocksCursor> {
+    Block { block_id: block::Id, block_bytes: Bytes, cursor: C, },
+    /// The block could not be decoded: its checksum does not match, it fails
+    /// to decrypt or to decompress. The iteration goes on past it.
+    Corrupted { block_id: block::Id, cursor: C, },
+    NoMoreBlocks,
+}
+
+impl<C> IterBlocks<C> {
+    /// Turns the raw blocks receiver into a stream which ends cleanly only
+    /// after `IterBlocksItem::NoMoreBlocks` and yields
+    /// `IterBlocksError::Interrupted` if the iteration is aborted on the
+    /// server side. A corrupted block is yielded as
+    /// `IterBlocksError::Corrupted` and the stream goes on.
+    pub fn into_stream(self) -> IterBlocksStream<C> {
+        IterBlocksStream {
+            blocks_rx: Some(self.blocks_rx),
+        }
+    }
 }
 
 #[derive(Debug)]
-pub enum IterBlocksError {
-    GenServer(ero::NoProcError),
+pub struct IterBlocksStream<C = IterBlocksCursor> {
+    blocks_rx: Option<mpsc::Receiver<IterBlocksItem<C>>>,
+}
+
+impl<C> Stream for IterBlocksStream<C> {
+    type Item = Result<(block::Id, Bytes), IterBlocksError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let blocks_rx = match self.blocks_rx.as_mut() {
+            None =>
+                return Poll::Ready(None),
+            Some(blocks_rx) =>
+                blocks_rx,
+        };
+        match blocks_rx.poll_next_unpin(cx) {
+            Poll::Pending =>
+                Poll::Pending,
+            Poll::Ready(Some(IterBlocksItem::Block { block_id, block_bytes, .. })) =>
+                Poll::Ready(Some(Ok((block_id, block_bytes)))),
+            Poll::Ready(Some(IterBlocksItem::Corrupted { block_id, .. })) =>
+                Poll::Ready(Some(Err(IterBlocksError::Corrupted { block_id, }))),
+            Poll::Ready(Some(IterBlocksItem::NoMoreBlocks)) => {
+                self.blocks_rx = None;
+                Poll::Ready(None)
+            },
+            Poll::Ready(None) => {
+                self.blocks_rx = None;
+                Poll::Ready(Some(Err(IterBlocksError::Interrupted)))
+            },
+        }
+    }
+}
+
+impl<C> FusedStream for IterBlocksStream<C> {
+    fn is_terminated(&self) -> bool {
+        self.blocks_rx.is_none()
+    }
 }
 
 #[derive(Debug)]
-pub struct IterBlocks {
+pub struct IterBlockIds {
     pub blocks_total_count: usize,
     pub blocks_total_size: usize,
-    pub blocks_rx: mpsc::Receiver<IterBlocksItem>,
+    pub block_ids_rx: mpsc::Receiver<IterBlockIdsItem>,
 }
 
 #[derive(Debug)]
-pub enum IterBlocksItem {
-    Block { block_id: block::Id, block_bytes: Bytes, },
+pub enum IterBlockIdsItem {
+    /// `block_size` is the size the block takes on the wheel: with
+    /// compression, checksums or encryption enabled it is the size of the
+    /// encoded block rather than the size originally written.
+    BlockId { block_id: block::Id, block_size: usize, },
     NoMoreBlocks,
 }
 
+/// Position in a blocks iteration right after the block it came with.
+///
+/// Pass it to [`Pid::iter_blocks_from`] to resume an interrupted iteration:
+/// the cursor wraps the blockwheel-fs iterator position, so the resumed
+/// iteration continues from there without reading the blocks before it
+/// again. The cursor is opaque and can be persisted by a long running job.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IterBlocksCursor {
+    iterator_next: blockwheel_fs::IterBlocksIterator,
+}
+
+impl IterBlocksCursor {
+    pub(crate) fn new(iterator_next: blockwheel_fs::IterBlocksIterator) -> IterBlocksCursor {
+        IterBlocksCursor { iterator_next, }
+    }
+}
+
+/// Reply of a request submitted with one of `Pid::submit_*` methods.
+pub type Reply<T, E> = future::BoxFuture<'static, Result<T, E>>;
+
 impl Pid {
-    pub async fn info(&mut self) -> Result<Info, ero::NoProcError> {
-        loop {
-            let (reply_tx, reply_rx) = oneshot::channel();
-            self.request_tx.send(proto::Request::Info(proto::RequestInfo { reply_tx, })).await
-                .map_err(|_send_error| ero::NoProcError)?;
-            match reply_rx.await {
-                Ok(info) =>
-                    return Ok(info),
-                Err(oneshot::Canceled) =>
-                    (),
-            }
+    /// Returns a copy of this `Pid` whose requests fail with a `Timeout`
+    /// error unless completed within `timeout`, retries included.
+    pub fn with_timeout(&self, timeout: Duration) -> Pid {
+        Pid {
+            request_tx: self.request_tx.clone(),
+            resubmit_tx: self.resubmit_tx.clone(),
+            timeout: Some(timeout),
+            counters: self.counters.clone(),
+            health_rx: self.health_rx.clone(),
         }
     }
 
-    pub async fn flush(&mut self) -> Result<Flushed, ero::NoProcError> {
-        loop {
-            let (reply_tx, reply_rx) = oneshot::channel();
-            self.request_tx.send(proto::Request::Flush(proto::RequestFlush { reply_tx, })).await
-                .map_err(|_send_error| ero::NoProcError)?;
-            match reply_rx.await {
-                Ok(Flushed) =>
-                    return Ok(Flushed),
-                Err(oneshot::Canceled) =>
-                    (),
-            }
-        }
+    pub async fn info(&mut self) -> Result<Info, InfoError> {
+        let span = trace::Span::request("info");
+        self.request(
+            &span,
+            |counters| &counters.info,
+            |reply_tx| proto::Request::Info(proto::RequestInfo { reply_tx, }),
+            Ok,
+        ).await
+    }
+
+    pub async fn flush(&mut self) -> Result<Flushed, FlushError> {
+        let span = trace::Span::request("flush");
+        self.request(
+            &span,
+            |counters| &counters.flush,
+            |reply_tx| proto::Request::Flush(proto::RequestFlush { reply_tx, }),
+            Ok,
+        ).await
     }
 
+    /// Writes a block once: a request lost in flight is not resubmitted and
+    /// is reported as `WriteBlockError::OutcomeUnknown` instead, use
+    /// [`Pid::write_block_with_token`] for safely retried writes.
     pub async fn write_block(&mut self, block_bytes: Bytes) -> Result<block::Id, WriteBlockError> {
-        loop {
+        let span = trace::Span::request("write_block");
+        span.record_block_size(block_bytes.len());
+        let block_size = block_bytes.len();
+        let request_span = span.clone();
+        let request_tx = &mut self.request_tx;
+        let started_at = Instant::now();
+        let result = with_deadline(self.timeout, WriteBlockError::Timeout, async move {
             let (reply_tx, reply_rx) = oneshot::channel();
-            self.request_tx
+            request_tx
                 .send(proto::Request::WriteBlock(proto::RequestWriteBlock {
-                    block_bytes: block_bytes.clone(),
-                    reply_tx,
+                    block_bytes,
+                    reply_tx: proto::ReplyTx::new(reply_tx, request_span),
                 }))
                 .await
                 .map_err(|_send_error| WriteBlockError::GenServer(ero::NoProcError))?;
 
             match reply_rx.await {
                 Ok(Ok(block_id)) =>
-                    return Ok(block_id),
+                    Ok(block_id),
                 Ok(Err(RequestWriteBlockError::NoSpaceLeft)) =>
-                    return Err(WriteBlockError::NoSpaceLeft),
+                    Err(WriteBlockError::NoSpaceLeft),
                 Err(oneshot::Canceled) =>
-                    (),
+                    Err(WriteBlockError::OutcomeUnknown),
             }
+        }).await;
+        self.counters.write_block.record(&result, started_at.elapsed());
+        span.record_outcome(&result);
+        if let Ok(block_id) = &result {
+            span.record_block_id(block_id);
+            self.counters.bytes_written.fetch_add(block_size as u64, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Writes a block identified by a client supplied `write_token`.
+    ///
+    /// The request is resubmitted if lost in flight, and the server replies
+    /// with the already written block id for a token it has seen before, so
+    /// no duplicate blocks are produced. If the write was in flight when the
+    /// blockwheel-fs meister failed, `WriteBlockError::OutcomeUnknown` is
+    /// returned for that token.
+    pub async fn write_block_with_token(
+        &mut self,
+        write_token: WriteToken,
+        block_bytes: Bytes,
+    )
+        -> Result<block::Id, WriteBlockError>
+    {
+        let span = trace::Span::request("write_block_with_token");
+        span.record_block_size(block_bytes.len());
+        let block_size = block_bytes.len();
+        let result = self.request(
+            &span,
+            |counters| &counters.write_block,
+            |reply_tx| proto::Request::WriteBlockWithToken(proto::RequestWriteBlockWithToken {
+                write_token,
+                block_bytes: block_bytes.clone(),
+                reply_tx,
+            }),
+            |reply| match reply {
+                Ok(block_id) =>
+                    Ok(block_id),
+                Err(proto::WriteBlockWithTokenError::NoSpaceLeft) =>
+                    Err(WriteBlockError::NoSpaceLeft),
+                Err(proto::WriteBlockWithTokenError::OutcomeUnknown) =>
+                    Err(WriteBlockError::OutcomeUnknown),
+            },
+        ).await;
+        if let Ok(block_id) = &result {
+            span.record_block_id(block_id);
+            self.counters.bytes_written.fetch_add(block_size as u64, Ordering::Relaxed);
         }
+        result
     }
 
     pub async fn read_block(&mut self, block_id: block::Id) -> Result<Bytes, ReadBlockError> {
-        loop {
+        let span = trace::Span::request("read_block");
+        span.record_block_id(&block_id);
+        let result = self.request(
+            &span,
+            |counters| &counters.read_block,
+            |reply_tx| proto::Request::ReadBlock(proto::RequestReadBlock {
+                block_id: block_id.clone(),
+                reply_tx,
+            }),
+            |reply| reply.map_err(ReadBlockError::from),
+        ).await;
+        if let Ok(block_bytes) = &result {
+            self.counters.bytes_read.fetch_add(block_bytes.len() as u64, Ordering::Relaxed);
+        }
+        result
+    }
+
+    pub async fn delete_block(&mut self, block_id: block::Id) -> Result<Deleted, DeleteBlockError> {
+        let span = trace::Span::request("delete_block");
+        span.record_block_id(&block_id);
+        self.request(
+            &span,
+            |counters| &counters.delete_block,
+            |reply_tx| proto::Request::DeleteBlock(proto::RequestDeleteBlock {
+                block_id: block_id.clone(),
+                reply_tx,
+            }),
+            |reply| match reply {
+                Ok(Deleted) =>
+                    Ok(Deleted),
+                Err(RequestDeleteBlockError::NotFound) =>
+                    Err(DeleteBlockError::NotFound),
+            },
+        ).await
+    }
+
+    pub async fn iter_blocks(&mut self) -> Result<IterBlocks, IterBlocksError> {
+        self.iter_blocks_request(None).await
+    }
+
+    /// Resumes blocks iteration right after the `cursor` position.
+    ///
+    /// `blocks_total_count` and `blocks_total_size` of the reply still
+    /// describe the whole wheel.
+    pub async fn iter_blocks_from(&mut self, cursor: IterBlocksCursor) -> Result<IterBlocks, IterBlocksError> {
+        self.iter_blocks_request(Some(cursor.iterator_next)).await
+    }
+
+    async fn iter_blocks_request(
+        &mut self,
+        iterator_next: Option<blockwheel_fs::IterBlocksIterator>,
+    )
+        -> Result<IterBlocks, IterBlocksError>
+    {
+        let span = trace::Span::request("iter_blocks");
+        self.request(
+            &span,
+            |counters| &counters.iter_blocks,
+            |reply_tx| proto::Request::IterBlocks(proto::RequestIterBlocks {
+                iterator_next: iterator_next.clone(),
+                reply_tx,
+            }),
+            Ok,
+        ).await
+    }
+
+    /// Iterates over ids and sizes of all blocks without transferring their
+    /// contents to the client.
+    ///
+    /// blockwheel-fs still loads every block while iterating, but the
+    /// payload is dropped as soon as it is handed over, so it is neither
+    /// decoded nor sent through the gen server.
+    pub async fn iter_block_ids(&mut self) -> Result<IterBlockIds, IterBlocksError> {
+        let span = trace::Span::request("iter_block_ids");
+        self.request(
+            &span,
+            |counters| &counters.iter_blocks,
+            |reply_tx| proto::Request::IterBlockIds(proto::RequestIterBlockIds { reply_tx, }),
+            Ok,
+        ).await
+    }
+
+    /// Reads every block of the wheel and verifies its checksum, reporting
+    /// the ids of corrupted blocks. The pass is restarted from scratch if the
+    /// gen server restarts in the middle of it, and abandoned if it shuts
+    /// down, in which case a `GenServer` error is returned.
+    pub async fn scrub(&mut self) -> Result<ScrubReport, IterBlocksError> {
+        let span = trace::Span::request("scrub");
+        self.request(
+            &span,
+            |counters| &counters.iter_blocks,
+            |reply_tx| proto::Request::Scrub(proto::RequestScrub { reply_tx, }),
+            Ok,
+        ).await
+    }
+
+    /// Writes all `blocks_bytes` with a single request, returning per block
+    /// results in the same order.
+    pub async fn write_blocks(
+        &mut self,
+        blocks_bytes: Vec<Bytes>,
+    )
+        -> Result<Vec<Result<block::Id, WriteBlockError>>, WriteBlockError>
+    {
+        let span = trace::Span::request("write_blocks");
+        let blocks_sizes: Vec<usize> = blocks_bytes.iter()
+            .map(|block_bytes| block_bytes.len())
+            .collect();
+        let request_span = span.clone();
+        let request_tx = &mut self.request_tx;
+        let started_at = Instant::now();
+        let blocks_count = blocks_bytes.len();
+        let result = with_deadline(self.timeout, WriteBlockError::Timeout, async move {
+            // writes are not resubmitted, so a lost item or a lost batch
+            // reply leaves the outcome unknown
             let (reply_tx, reply_rx) = oneshot::channel();
-            self.request_tx
-                .send(proto::Request::ReadBlock(proto::RequestReadBlock {
-                    block_id: block_id.clone(),
-                    reply_tx,
+            request_tx
+                .send(proto::Request::WriteBlocks(proto::RequestWriteBlocks {
+                    blocks_bytes,
+                    reply_tx: proto::ReplyTx::new(reply_tx, request_span),
                 }))
                 .await
-                .map_err(|_send_error| ReadBlockError::GenServer(ero::NoProcError))?;
+                .map_err(|_send_error| WriteBlockError::GenServer(ero::NoProcError))?;
 
             match reply_rx.await {
-                Ok(Ok(block_bytes)) =>
-                    return Ok(block_bytes),
-                Ok(Err(RequestReadBlockError::NotFound)) =>
-                    return Err(ReadBlockError::NotFound),
+                Ok(replies) =>
+                    Ok(replies.into_iter()
+                        .map(|reply| match reply {
+                            Some(Ok(block_id)) =>
+                                Ok(block_id),
+                            Some(Err(RequestWriteBlockError::NoSpaceLeft)) =>
+                                Err(WriteBlockError::NoSpaceLeft),
+                            None =>
+                                Err(WriteBlockError::OutcomeUnknown),
+                        })
+                        .collect()),
                 Err(oneshot::Canceled) =>
-                    (),
+                    Ok((0 .. blocks_count).map(|_| Err(WriteBlockError::OutcomeUnknown)).collect()),
             }
+        }).await;
+        let elapsed = started_at.elapsed();
+        span.record_outcome(&result);
+        match &result {
+            Ok(items) =>
+                for (item, block_size) in items.iter().zip(blocks_sizes) {
+                    self.counters.write_block.record(item, elapsed);
+                    if item.is_ok() {
+                        self.counters.bytes_written.fetch_add(block_size as u64, Ordering::Relaxed);
+                    }
+                },
+            Err(error) =>
+                self.counters.write_block.record(error, elapsed),
         }
+        result
     }
 
-    pub async fn delete_block(&mut self, block_id: block::Id) -> Result<Deleted, DeleteBlockError> {
-        loop {
-            let (reply_tx, reply_rx) = oneshot::channel();
-            self.request_tx
-                .send(proto::Request::DeleteBlock(proto::RequestDeleteBlock {
-                    block_id: block_id.clone(),
-                    reply_tx,
-                }))
-                .await
-                .map_err(|_send_error| DeleteBlockError::GenServer(ero::NoProcError))?;
+    /// Reads all `block_ids` with a single request, returning per block
+    /// results in the same order.
+    pub async fn read_blocks(
+        &mut self,
+        block_ids: Vec<block::Id>,
+    )
+        -> Result<Vec<Result<Bytes, ReadBlockError>>, ReadBlockError>
+    {
+        let span = trace::Span::request("read_blocks");
+        let request_span = span.clone();
+        let request_tx = &mut self.request_tx;
+        let started_at = Instant::now();
+        let result = with_deadline(self.timeout, ReadBlockError::Timeout, async move {
+            let mut results: Vec<Option<Result<Bytes, ReadBlockError>>> =
+                block_ids.iter().map(|_| None).collect();
+            loop {
+                let pending: Vec<usize> = (0 .. results.len())
+                    .filter(|&index| results[index].is_none())
+                    .collect();
+                if pending.is_empty() {
+                    return Ok(results.into_iter().flatten().collect());
+                }
 
-            match reply_rx.await {
-                Ok(Ok(Deleted)) =>
-                    return Ok(Deleted),
-                Ok(Err(RequestDeleteBlockError::NotFound)) =>
-                    return Err(DeleteBlockError::NotFound),
-                Err(oneshot::Canceled) =>
-                    (),
+                let (reply_tx, reply_rx) = oneshot::channel();
+                request_tx
+                    .send(proto::Request::ReadBlocks(proto::RequestReadBlocks {
+                        block_ids: pending.iter()
+                            .map(|&index| block_ids[index].clone())
+                            .collect(),
+                        reply_tx: proto::ReplyTx::new(reply_tx, request_span.clone()),
+                    }))
+                    .await
+                    .map_err(|_send_error| ReadBlockError::GenServer(ero::NoProcError))?;
+
+                match reply_rx.await {
+                    Ok(replies) =>
+                        for (index, reply) in pending.into_iter().zip(replies) {
+                            results[index] = match reply {
+                                Some(reply) =>
+                                    Some(reply.map_err(ReadBlockError::from)),
+                                None =>
+                                    None,
+                            };
+                        },
+                    Err(oneshot::Canceled) =>
+                        (),
+                }
             }
+        }).await;
+        let elapsed = started_at.elapsed();
+        span.record_outcome(&result);
+        match &result {
+            Ok(items) =>
+                for item in items {
+                    self.counters.read_block.record(item, elapsed);
+                    if let Ok(block_bytes) = item {
+                        self.counters.bytes_read.fetch_add(block_bytes.len() as u64, Ordering::Relaxed);
+                    }
+                },
+            Err(error) =>
+                self.counters.read_block.record(error, elapsed),
         }
+        result
     }
 
-    pub async fn iter_blocks(&mut self) -> Result<IterBlocks, IterBlocksError> {
-        loop {
-            let (reply_tx, reply_rx) = oneshot::channel();
-            self.request_tx
-                .send(proto::Request::IterBlocks(proto::RequestIterBlocks {
-                    reply_tx,
-                }))
-                .await
-                .map_err(|_send_error| IterBlocksError::GenServer(ero::NoProcError))?;
+    /// Deletes all `block_ids` with a single request, returning per block
+    /// results in the same order.
+    pub async fn delete_blocks(
+        &mut self,
+        block_ids: Vec<block::Id>,
+    )
+        -> Result<Vec<Result<Deleted, DeleteBlockError>>, DeleteBlockError>
+    {
+        let span = trace::Span::request("delete_blocks");
+        let request_span = span.clone();
+        let request_tx = &mut self.request_tx;
+        let started_at = Instant::now();
+        let result = with_deadline(self.timeout, DeleteBlockError::Timeout, async move {
+            let mut results: Vec<Option<Result<Deleted, DeleteBlockError>>> =
+                block_ids.iter().map(|_| None).collect();
+            loop {
+                let pending: Vec<usize> = (0 .. results.len())
+                    .filter(|&index| results[index].is_none())
+                    .collect();
+                if pending.is_empty() {
+                    return Ok(results.into_iter().flatten().collect());
+                }
 
-            match reply_rx.await {
-                Ok(iter_blocks) =>
-                    return Ok(iter_blocks),
-                Err(oneshot::Canceled) =>
-                    (),
+                let (reply_tx, reply_rx) = oneshot::channel();
+                request_tx
+                    .send(proto::Request::DeleteBlocks(proto::RequestDeleteBlocks {
+                        block_ids: pending.iter()
+                            .map(|&index| block_ids[index].clone())
+                            .collect(),
+                        reply_tx: proto::ReplyTx::new(reply_tx, request_span.clone()),
+                    }))
+                    .await
+                    .map_err(|_send_error| DeleteBlockError::GenServer(ero::NoProcError))?;
+
+                match reply_rx.await {
+                    Ok(replies) =>
+                        for (index, reply) in pending.into_iter().zip(replies) {
+                            results[index] = match reply {
+                                Some(Ok(Deleted)) =>
+                                    Some(Ok(Deleted)),
+                                Some(Err(RequestDeleteBlockError::NotFound)) =>
+                                    Some(Err(DeleteBlockError::NotFound)),
+                                None =>
+                                    None,
+                            };
+                        },
+                    Err(oneshot::Canceled) =>
+                        (),
+                }
             }
+        }).await;
+        let elapsed = started_at.elapsed();
+        span.record_outcome(&result);
+        match &result {
+            Ok(items) =>
+                for item in items {
+                    self.counters.delete_block.record(item, elapsed);
+                },
+            Err(error) =>
+                self.counters.delete_block.record(error, elapsed),
         }
+        result
+    }
+
+    /// Enqueues a block write and returns as soon as the request is accepted,
+    /// so several requests can be kept in flight from a single task.
+    ///
+    /// The timeout covers both the submission and the reply, counting from
+    /// this call.
+    pub async fn submit_write_block(
+        &mut self,
+        block_bytes: Bytes,
+    )
+        -> Result<Reply<block::Id, WriteBlockError>, WriteBlockError>
+    {
+        let span = trace::Span::request("submit_write_block");
+        span.record_block_size(block_bytes.len());
+        let block_size = block_bytes.len();
+        let started_at = Instant::now();
+        let deadline = self.deadline();
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let request_tx = &mut self.request_tx;
+        let request = proto::Request::WriteBlock(proto::RequestWriteBlock {
+            block_bytes,
+            reply_tx: proto::ReplyTx::new(reply_tx, span.clone()),
+        });
+        with_deadline_at(deadline, WriteBlockError::Timeout, async move {
+            request_tx.send(request).await
+                .map_err(|_send_error| WriteBlockError::GenServer(ero::NoProcError))
+        }).await?;
+
+        let counters = self.counters.clone();
+        Ok(Box::pin(async move {
+            let result = with_deadline_at(deadline, WriteBlockError::Timeout, async move {
+                match reply_rx.await {
+                    Ok(Ok(block_id)) =>
+                        Ok(block_id),
+                    Ok(Err(RequestWriteBlockError::NoSpaceLeft)) =>
+                        Err(WriteBlockError::NoSpaceLeft),
+                    Err(oneshot::Canceled) =>
+                        Err(WriteBlockError::OutcomeUnknown),
+                }
+            }).await;
+            counters.write_block.record(&result, started_at.elapsed());
+            span.record_outcome(&result);
+            if result.is_ok() {
+                counters.bytes_written.fetch_add(block_size as u64, Ordering::Relaxed);
+            }
+            result
+        }))
+    }
+
+    /// Enqueues a block read and returns as soon as the request is accepted,
+    /// so several requests can be kept in flight from a single task.
+    ///
+    /// The timeout covers the submission, the reply and the resubmissions of
+    /// a lost request, counting from this call.
+    pub async fn submit_read_block(
+        &mut self,
+        block_id: block::Id,
+    )
+        -> Result<Reply<Bytes, ReadBlockError>, ReadBlockError>
+    {
+        let span = trace::Span::request("submit_read_block");
+        span.record_block_id(&block_id);
+        let started_at = Instant::now();
+        let deadline = self.deadline();
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let request_tx = &mut self.request_tx;
+        let request = proto::Request::ReadBlock(proto::RequestReadBlock {
+            block_id: block_id.clone(),
+            reply_tx: proto::ReplyTx::new(reply_tx, span.clone()),
+        });
+        with_deadline_at(deadline, ReadBlockError::Timeout, async move {
+            request_tx.send(request).await
+                .map_err(|_send_error| ReadBlockError::GenServer(ero::NoProcError))
+        }).await?;
+
+        let counters = self.counters.clone();
+        let resubmit_tx = self.resubmit_tx.clone();
+        let request_span = span.clone();
+        Ok(Box::pin(async move {
+            let result = with_deadline_at(deadline, ReadBlockError::Timeout, async move {
+                let mut reply_rx = reply_rx;
+                loop {
+                    match reply_rx.await {
+                        Ok(reply) =>
+                            return reply.map_err(ReadBlockError::from),
+                        Err(oneshot::Canceled) =>
+                            (),
+                    }
+                    let (reply_tx, next_reply_rx) = oneshot::channel();
+                    resubmit_tx.lock().await
+                        .send(proto::Request::ReadBlock(proto::RequestReadBlock {
+                            block_id: block_id.clone(),
+                            reply_tx: proto::ReplyTx::new(reply_tx, request_span.clone()),
+                        }))
+                        .await
+                        .map_err(|_send_error| ReadBlockError::GenServer(ero::NoProcError))?;
+                    reply_rx = next_reply_rx;
+                }
+            }).await;
+            counters.read_block.record(&result, started_at.elapsed());
+            span.record_outcome(&result);
+            if let Ok(block_bytes) = &result {
+                counters.bytes_read.fetch_add(block_bytes.len() as u64, Ordering::Relaxed);
+            }
+            result
+        }))
+    }
+
+    /// Enqueues a block delete and returns as soon as the request is accepted,
+    /// so several requests can be kept in flight from a single task.
+    ///
+    /// The timeout covers the submission, the reply and the resubmissions of
+    /// a lost request, counting from this call.
+    pub async fn submit_delete_block(
+        &mut self,
+        block_id: block::Id,
+    )
+        -> Result<Reply<Deleted, DeleteBlockError>, DeleteBlockError>
+    {
+        let span = trace::Span::request("submit_delete_block");
+        span.record_block_id(&block_id);
+        let started_at = Instant::now();
+        let deadline = self.deadline();
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let request_tx = &mut self.request_tx;
+        let request = proto::Request::DeleteBlock(proto::RequestDeleteBlock {
+            block_id: block_id.clone(),
+            reply_tx: proto::ReplyTx::new(reply_tx, span.clone()),
+        });
+        with_deadline_at(deadline, DeleteBlockError::Timeout, async move {
+            request_tx.send(request).await
+                .map_err(|_send_error| DeleteBlockError::GenServer(ero::NoProcError))
+        }).await?;
+
+        let counters = self.counters.clone();
+        let resubmit_tx = self.resubmit_tx.clone();
+        let request_span = span.clone();
+        Ok(Box::pin(async move {
+            let result = with_deadline_at(deadline, DeleteBlockError::Timeout, async move {
+                let mut reply_rx = reply_rx;
+                loop {
+                    match reply_rx.await {
+                        Ok(Ok(Deleted)) =>
+                            return Ok(Deleted),
+                        Ok(Err(RequestDeleteBlockError::NotFound)) =>
+                            return Err(DeleteBlockError::NotFound),
+                        Err(oneshot::Canceled) =>
+                            (),
+                    }
+                    let (reply_tx, next_reply_rx) = oneshot::channel();
+                    resubmit_tx.lock().await
+                        .send(proto::Request::DeleteBlock(proto::RequestDeleteBlock {
+                            block_id: block_id.clone(),
+                            reply_tx: proto::ReplyTx::new(reply_tx, request_span.clone()),
+                        }))
+                        .await
+                        .map_err(|_send_error| DeleteBlockError::GenServer(ero::NoProcError))?;
+                    reply_rx = next_reply_rx;
+                }
+            }).await;
+            counters.delete_block.record(&result, started_at.elapsed());
+            span.record_outcome(&result);
+            result
+        }))
+    }
+
+    /// Sends the request built by `make_request`, resubmitting it whenever
+    /// its reply is lost, and turns the reply into the result with
+    /// `on_reply`. The outcome is recorded in the counters picked by
+    /// `op_counters` and in the request `span`.
+    async fn request<R, T, E, B, F>(
+        &mut self,
+        span: &trace::Span,
+        op_counters: fn(&stats::Counters) -> &stats::OpCounters,
+        make_request: B,
+        on_reply: F,
+    )
+        -> Result<T, E>
+    where B: Fn(proto::ReplyTx<R>) -> proto::Request,
+          F: Fn(R) -> Result<T, E>,
+          E: RequestError + stats::Classify,
+    {
+        let request_tx = &mut self.request_tx;
+        let started_at = Instant::now();
+        let result = with_deadline(self.timeout, E::timeout(), async {
+            loop {
+                let (reply_tx, reply_rx) = oneshot::channel();
+                request_tx
+                    .send(make_request(proto::ReplyTx::new(reply_tx, span.clone())))
+                    .await
+                    .map_err(|_send_error| E::gen_server(ero::NoProcError))?;
+                match reply_rx.await {
+                    Ok(reply) =>
+                        return on_reply(reply),
+                    Err(oneshot::Canceled) =>
+                        (),
+                }
+            }
+        }).await;
+        op_counters(&self.counters).record(&result, started_at.elapsed());
+        span.record_outcome(&result);
+        result
+    }
+
+    fn deadline(&self) -> Option<tokio::time::Instant> {
+        self.timeout.map(|timeout| tokio::time::Instant::now() + timeout)
+    }
+
+    /// Current lifecycle state of the gen server.
+    pub fn health(&self) -> Health {
+        self.health_rx.borrow().clone()
+    }
+
+    /// Receiver notified on every gen server lifecycle change.
+    pub fn health_watch(&self) -> watch::Receiver<Health> {
+        self.health_rx.clone()
+    }
+
+    /// Returns a snapshot of the server operation counters.
+    pub async fn stats(&mut self) -> Result<stats::Stats, StatsError> {
+        let span = trace::Span::request("stats");
+        let request_tx = &mut self.request_tx;
+        with_deadline(self.timeout, StatsError::Timeout, async move {
+            loop {
+                let (reply_tx, reply_rx) = oneshot::channel();
+                request_tx
+                    .send(proto::Request::Stats(proto::RequestStats {
+                        reply_tx: proto::ReplyTx::new(reply_tx, span.clone()),
+                    }))
+                    .await
+                    .map_err(|_send_error| StatsError::GenServer(ero::NoProcError))?;
+                match reply_rx.await {
+                    Ok(stats) =>
+                        return Ok(stats),
+                    Err(oneshot::Canceled) =>
+                        (),
+                }
+            }
+        }).await
+    }
+
+    /// Asks the server to stop gracefully.
+    ///
+    /// The request channel is closed right away, so any subsequent request
+    /// from any `Pid` fails with a `GenServer` error. Requests accepted before
+    /// the shutdown are still served and running blocks iterators are allowed
+    /// to reach their end, then a final flush is performed before this call
+    /// resolves.
+    ///
+    /// The timeout of this `Pid` bounds the wait for iterators: the ones still
+    /// running when it expires are closed, so their streams end with
+    /// `IterBlocksError::Interrupted`, and the final flush is awaited anyway.
+    /// Without a timeout the iterators are waited for until they finish.
+    pub async fn shutdown(&mut self) -> Result<Terminated, ShutdownError> {
+        let span = trace::Span::request("shutdown");
+        let deadline = self.deadline();
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let request_tx = &mut self.request_tx;
+        let request = proto::Request::Shutdown(proto::RequestShutdown {
+            iterators_deadline: deadline,
+            reply_tx: proto::ReplyTx::new(reply_tx, span),
+        });
+        with_deadline_at(deadline, ShutdownError::Timeout, async move {
+            request_tx.send(request).await
+                .map_err(|_send_error| ShutdownError::GenServer(ero::NoProcError))
+        }).await?;
+        reply_rx.await
+            .map_err(|oneshot::Canceled| ShutdownError::GenServer(ero::NoProcError))
+    }
+}
+
+async fn with_deadline_at<F, T, E>(deadline: Option<tokio::time::Instant>, timeout_error: E, future: F) -> Result<T, E>
+where F: Future<Output = Result<T, E>>,
+{
+    match deadline {
+        None =>
+            future.await,
+        Some(deadline) =>
+            match tokio::time::timeout_at(deadline, future).await {
+                Ok(result) =>
+                    result,
+                Err(_elapsed) =>
+                    Err(timeout_error),
+            },
+    }
+}
+
+async fn with_deadline<F, T, E>(timeout: Option<Duration>, timeout_error: E, future: F) -> Result<T, E>
+where F: Future<Output = Result<T, E>>,
+{
+    match timeout {
+        None =>
+            future.await,
+        Some(timeout) =>
+            match tokio::time::timeout(timeout, future).await {
+                Ok(result) =>
+                    result,
+                Err(_elapsed) =>
+                    Err(timeout_error),
+            },
+    }
+}
+
+/// Failures shared by every request sent with `Pid::request` or through a
+/// [`BlockingPid`].
+trait RequestError {
+    fn gen_server(error: ero::NoProcError) -> Self;
+    fn timeout() -> Self;
+    fn thread_pool_worker() -> Self;
+}
+
+impl RequestError for InfoError {
+    fn gen_server(error: ero::NoProcError) -> InfoError {
+        InfoError::GenServer(error)
+    }
+
+    fn timeout() -> InfoError {
+        InfoError::Timeout
+    }
+
+    fn thread_pool_worker() -> InfoError {
+        InfoError::ThreadPoolWorker
+    }
+}
+
+impl RequestError for FlushError {
+    fn gen_server(error: ero::NoProcError) -> FlushError {
+        FlushError::GenServer(error)
+    }
+
+    fn timeout() -> FlushError {
+        FlushError::Timeout
+    }
+
+    fn thread_pool_worker() -> FlushError {
+        FlushError::ThreadPoolWorker
+    }
+}
+
+impl RequestError for WriteBlockError {
+    fn gen_server(error: ero::NoProcError) -> WriteBlockError {
+        WriteBlockError::GenServer(error)
+    }
+
+    fn timeout() -> WriteBlockError {
+        WriteBlockError::Timeout
+    }
+
+    fn thread_pool_worker() -> WriteBlockError {
+        WriteBlockError::ThreadPoolWorker
+    }
+}
+
+impl RequestError for ReadBlockError {
+    fn gen_server(error: ero::NoProcError) -> ReadBlockError {
+        ReadBlockError::GenServer(error)
+    }
+
+    fn timeout() -> ReadBlockError {
+        ReadBlockError::Timeout
+    }
+
+    fn thread_pool_worker() -> ReadBlockError {
+        ReadBlockError::ThreadPoolWorker
+    }
+}
+
+impl RequestError for DeleteBlockError {
+    fn gen_server(error: ero::NoProcError) -> DeleteBlockError {
+        DeleteBlockError::GenServer(error)
+    }
+
+    fn timeout() -> DeleteBlockError {
+        DeleteBlockError::Timeout
+    }
+
+    fn thread_pool_worker() -> DeleteBlockError {
+        DeleteBlockError::ThreadPoolWorker
+    }
+}
+
+impl RequestError for IterBlocksError {
+    fn gen_server(error: ero::NoProcError) -> IterBlocksError {
+        IterBlocksError::GenServer(error)
+    }
+
+    fn timeout() -> IterBlocksError {
+        IterBlocksError::Timeout
+    }
+
+    fn thread_pool_worker() -> IterBlocksError {
+        IterBlocksError::ThreadPoolWorker
     }
 }
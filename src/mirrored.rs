@@ -0,0 +1,319 @@
+use std::{
+    sync::{
+        Arc,
+        Mutex,
+    },
+    collections::{
+        HashMap,
+    },
+};
+
+use futures::{
+    future,
+    StreamExt,
+};
+
+use alloc_pool::{
+    bytes::{
+        Bytes,
+        BytesPool,
+    },
+};
+
+use crate::{
+    block,
+    Pid,
+    Flushed,
+    Deleted,
+    FlushError,
+    ResyncError,
+    ReadBlockError,
+    IterBlocksError,
+    WriteBlockError,
+    DeleteBlockError,
+};
+
+/// Prefix of every block written to the secondary wheel, followed by the
+/// link version and the id of the primary copy.
+const LINK_MAGIC: &[u8; 4] = b"bwmr";
+const LINK_VERSION: u8 = 1;
+
+/// Frontend writing every block to both a primary and a secondary wheel.
+///
+/// Blocks are addressed by their primary `block::Id`. Each secondary copy
+/// is prefixed with the id of its primary copy, so the id mapping is stored
+/// in the secondary wheel itself and [`MirroredPid::open`] rebuilds it.
+/// Secondary blocks without that prefix are not touched.
+#[derive(Clone)]
+pub struct MirroredPid {
+    primary: Pid,
+    secondary: Pid,
+    blocks_pool: BytesPool,
+    mapping: Arc<Mutex<HashMap<block::Id, block::Id>>>,
+}
+
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+pub struct ResyncReport {
+    /// Blocks found on both wheels.
+    pub matched: usize,
+    /// Blocks present only on the primary and copied to the secondary.
+    pub copied_to_secondary: usize,
+    /// Blocks lost by the primary and copied back from the secondary. They
+    /// get new primary ids.
+    pub copied_to_primary: usize,
+}
+
+impl MirroredPid {
+    /// Rebuilds the id mapping from the secondary wheel contents.
+    pub async fn open(primary: Pid, mut secondary: Pid, blocks_pool: BytesPool) -> Result<MirroredPid, IterBlocksError> {
+        let mut mapping = HashMap::new();
+        let mut secondary_blocks = secondary.iter_blocks().await?.into_stream();
+        while let Some(item) = secondary_blocks.next().await {
+            let (secondary_block_id, block_bytes) = match item {
+                Ok(block) =>
+                    block,
+                Err(IterBlocksError::Corrupted { block_id, }) => {
+                    log::warn!("skipping corrupted secondary block {:?}", block_id);
+                    continue;
+                },
+                Err(error) =>
+                    return Err(error),
+            };
+            if let Some((primary_block_id, _payload)) = decode_link(&block_bytes) {
+                // a duplicate copy left by an interrupted resync is removed
+                // by the next one
+                mapping.entry(primary_block_id)
+                    .or_insert(secondary_block_id);
+            }
+        }
+        Ok(MirroredPid {
+            primary,
+            secondary,
+            blocks_pool,
+            mapping: Arc::new(Mutex::new(mapping)),
+        })
+    }
+
+    pub async fn flush(&mut self) -> Result<Flushed, FlushError> {
+        let (primary_result, secondary_result) =
+            future::join(self.primary.flush(), self.secondary.flush()).await;
+        primary_result?;
+        secondary_result?;
+        Ok(Flushed)
+    }
+
+    /// Writes the block to the primary wheel and then its linked copy to the
+    /// secondary one, returning the primary id.
+    ///
+    /// A failed secondary write is only logged: the block is copied over by
+    /// the next [`MirroredPid::resync`].
+    pub async fn write_block(&mut self, block_bytes: Bytes) -> Result<block::Id, WriteBlockError> {
+        let primary_block_id = self.primary.write_block(block_bytes.clone()).await?;
+        if let Err(error) = self.write_secondary(&primary_block_id, &block_bytes).await {
+            log::warn!("secondary write failed for block {:?}: {:?}", primary_block_id, error);
+        }
+        Ok(primary_block_id)
+    }
+
+    /// Reads the block from the primary wheel, falling back to the
+    /// secondary one if the primary read fails.
+    pub async fn read_block(&mut self, block_id: block::Id) -> Result<Bytes, ReadBlockError> {
+        let primary_error = match self.primary.read_block(block_id.clone()).await {
+            Ok(block_bytes) =>
+                return Ok(block_bytes),
+            Err(error) =>
+                error,
+        };
+        let maybe_secondary_block_id = self.mapping.lock().unwrap()
+            .get(&block_id)
+            .cloned();
+        let secondary_block_id = match maybe_secondary_block_id {
+            None =>
+                return Err(primary_error),
+            Some(secondary_block_id) =>
+                secondary_block_id,
+        };
+        log::warn!("primary read failed for block {:?}: {:?}, using secondary", block_id, primary_error);
+        let block_bytes = self.secondary.read_block(secondary_block_id.clone()).await?;
+        match decode_link(&block_bytes) {
+            Some((primary_block_id, payload)) if primary_block_id == block_id =>
+                Ok(self.copy_bytes(payload)),
+            _ => {
+                log::error!("secondary block {:?} is not a copy of block {:?}", secondary_block_id, block_id);
+                Err(primary_error)
+            },
+        }
+    }
+
+    /// Deletes the secondary copy first and the primary block after it, so
+    /// an interrupted delete never leaves a secondary copy of a block deleted
+    /// from the primary.
+    pub async fn delete_block(&mut self, block_id: block::Id) -> Result<Deleted, DeleteBlockError> {
+        let maybe_secondary_block_id = self.mapping.lock().unwrap()
+            .get(&block_id)
+            .cloned();
+        if let Some(secondary_block_id) = maybe_secondary_block_id {
+            match self.secondary.delete_block(secondary_block_id).await {
+                Ok(Deleted) | Err(DeleteBlockError::NotFound) =>
+                    (),
+                Err(error) =>
+                    return Err(error),
+            }
+            self.mapping.lock().unwrap().remove(&block_id);
+        }
+        self.primary.delete_block(block_id).await
+    }
+
+    /// Copies blocks missing on either wheel to the other one and rebuilds
+    /// the id mapping.
+    ///
+    /// A secondary copy is matched to its primary block by the linked id and
+    /// by comparing the contents. A secondary copy whose primary block is
+    /// gone can only be left by a primary losing blocks, because deletes
+    /// remove the secondary copy first, so it is copied back to the primary.
+    pub async fn resync(&mut self) -> Result<ResyncReport, ResyncError> {
+        let mut report = ResyncReport::default();
+
+        let mut linked: HashMap<block::Id, Vec<block::Id>> = HashMap::new();
+        let mut secondary_blocks = self.secondary.iter_blocks().await
+            .map_err(ResyncError::IterBlocks)?
+            .into_stream();
+        while let Some(item) = secondary_blocks.next().await {
+            let (secondary_block_id, block_bytes) = match item {
+                Ok(block) =>
+                    block,
+                Err(IterBlocksError::Corrupted { block_id, }) => {
+                    // the primary block is copied over again
+                    log::warn!("skipping corrupted secondary block {:?}", block_id);
+                    continue;
+                },
+                Err(error) =>
+                    return Err(ResyncError::IterBlocks(error)),
+            };
+            if let Some((primary_block_id, _payload)) = decode_link(&block_bytes) {
+                linked.entry(primary_block_id)
+                    .or_default()
+                    .push(secondary_block_id);
+            }
+        }
+
+        let mut lost = Vec::new();
+        let mut primary_blocks = self.primary.iter_blocks().await
+            .map_err(ResyncError::IterBlocks)?
+            .into_stream();
+        while let Some(item) = primary_blocks.next().await {
+            let (primary_block_id, block_bytes) = match item {
+                Ok(block) =>
+                    block,
+                Err(IterBlocksError::Corrupted { block_id, }) => {
+                    // its secondary copy is left unmatched, so it is restored
+                    // under a new primary id
+                    log::warn!("skipping corrupted primary block {:?}", block_id);
+                    continue;
+                },
+                Err(error) =>
+                    return Err(ResyncError::IterBlocks(error)),
+            };
+            let mut maybe_secondary_block_id = None;
+            for secondary_block_id in linked.remove(&primary_block_id).unwrap_or_default() {
+                let secondary_bytes = match self.secondary.read_block(secondary_block_id.clone()).await {
+                    Ok(secondary_bytes) =>
+                        secondary_bytes,
+                    Err(ReadBlockError::NotFound) =>
+                        continue,
+                    Err(error) =>
+                        return Err(ResyncError::ReadBlock(error)),
+                };
+                let same_contents = decode_link(&secondary_bytes)
+                    .is_some_and(|(_primary_block_id, payload)| payload == &block_bytes[..]);
+                if !same_contents {
+                    // the primary id has been reused after the primary lost
+                    // the block this copy was made of
+                    lost.push(secondary_block_id);
+                } else if maybe_secondary_block_id.is_none() {
+                    maybe_secondary_block_id = Some(secondary_block_id);
+                } else {
+                    self.delete_secondary(secondary_block_id).await;
+                }
+            }
+            match maybe_secondary_block_id {
+                Some(secondary_block_id) => {
+                    self.mapping.lock().unwrap().insert(primary_block_id, secondary_block_id);
+                    report.matched += 1;
+                },
+                None => {
+                    self.write_secondary(&primary_block_id, &block_bytes).await
+                        .map_err(ResyncError::WriteBlock)?;
+                    report.copied_to_secondary += 1;
+                },
+            }
+        }
+        lost.extend(linked.into_values().flatten());
+
+        for secondary_block_id in lost {
+            let secondary_bytes = match self.secondary.read_block(secondary_block_id.clone()).await {
+                Ok(secondary_bytes) =>
+                    secondary_bytes,
+                Err(ReadBlockError::NotFound) =>
+                    continue,
+                Err(error) =>
+                    return Err(ResyncError::ReadBlock(error)),
+            };
+            let block_bytes = match decode_link(&secondary_bytes) {
+                Some((_primary_block_id, payload)) =>
+                    self.copy_bytes(payload),
+                None =>
+                    continue,
+            };
+            let primary_block_id = self.primary.write_block(block_bytes.clone()).await
+                .map_err(ResyncError::WriteBlock)?;
+            // the copy is linked to the new primary id before the old one is
+            // removed, so an interrupted resync leaves a duplicate at worst
+            self.write_secondary(&primary_block_id, &block_bytes).await
+                .map_err(ResyncError::WriteBlock)?;
+            self.delete_secondary(secondary_block_id).await;
+            report.copied_to_primary += 1;
+        }
+
+        Ok(report)
+    }
+
+    async fn write_secondary(&mut self, primary_block_id: &block::Id, block_bytes: &[u8]) -> Result<block::Id, WriteBlockError> {
+        let mut link_bytes = self.blocks_pool.lend();
+        link_bytes.extend_from_slice(LINK_MAGIC);
+        bincode::serialize_into(&mut *link_bytes, &(LINK_VERSION, primary_block_id))
+            .expect("mirror link serialization into memory buffer failed");
+        link_bytes.extend_from_slice(block_bytes);
+        let secondary_block_id = self.secondary.write_block(link_bytes.freeze()).await?;
+        self.mapping.lock().unwrap()
+            .insert(primary_block_id.clone(), secondary_block_id.clone());
+        Ok(secondary_block_id)
+    }
+
+    async fn delete_secondary(&mut self, secondary_block_id: block::Id) {
+        self.mapping.lock().unwrap()
+            .retain(|_primary_block_id, mapped_block_id| *mapped_block_id != secondary_block_id);
+        match self.secondary.delete_block(secondary_block_id.clone()).await {
+            Ok(Deleted) | Err(DeleteBlockError::NotFound) =>
+                (),
+            Err(error) =>
+                log::warn!("failed to delete stale secondary block {:?}: {:?}", secondary_block_id, error),
+        }
+    }
+
+    fn copy_bytes(&self, payload: &[u8]) -> Bytes {
+        let mut block_bytes = self.blocks_pool.lend();
+        block_bytes.extend_from_slice(payload);
+        block_bytes.freeze()
+    }
+}
+
+/// Splits a secondary block into the id of its primary copy and the payload.
+fn decode_link(block_bytes: &[u8]) -> Option<(block::Id, &[u8])> {
+    let mut link = block_bytes.strip_prefix(&LINK_MAGIC[..])?;
+    let (version, primary_block_id): (u8, block::Id) = bincode::deserialize_from(&mut link).ok()?;
+    if version != LINK_VERSION {
+        return None;
+    }
+    Some((primary_block_id, link))
+}
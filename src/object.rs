@@ -0,0 +1,221 @@
+use futures::{
+    stream::{
+        self,
+        BoxStream,
+    },
+    Stream,
+    StreamExt,
+};
+
+use serde::{
+    Serialize,
+    Deserialize,
+};
+
+use alloc_pool::{
+    bytes::{
+        Bytes,
+        BytesPool,
+    },
+};
+
+use crate::{
+    block,
+    Pid,
+    Deleted,
+    ReadObjectError,
+    WriteObjectError,
+    DeleteBlockError,
+    DeleteObjectError,
+};
+
+/// Prefix of every object manifest block, followed by the manifest version
+/// and the crc32c (u32 LE) of everything else in the block: the header
+/// fields before it and the bincode encoded [`Manifest`].
+const MANIFEST_MAGIC: &[u8; 4] = b"bwob";
+const MANIFEST_VERSION: u8 = 1;
+const MANIFEST_HEADER_SIZE: usize = MANIFEST_MAGIC.len() + 1 + 4;
+
+/// Object stored as a sequence of chunk blocks, listed by a manifest block
+/// whose id identifies the object.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Manifest {
+    object_size: u64,
+    chunks: Vec<block::Id>,
+}
+
+impl Pid {
+    /// Splits `object_bytes` into chunks of at most `chunk_size` bytes and
+    /// writes them followed by a manifest block, returning the manifest id.
+    pub async fn write_object(
+        &mut self,
+        blocks_pool: &BytesPool,
+        object_bytes: &[u8],
+        chunk_size: usize,
+    )
+        -> Result<block::Id, WriteObjectError>
+    {
+        if chunk_size == 0 {
+            return Err(WriteObjectError::ZeroChunkSize);
+        }
+        let chunks = object_bytes
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let mut chunk_bytes = blocks_pool.lend();
+                chunk_bytes.extend_from_slice(chunk);
+                chunk_bytes.freeze()
+            });
+        self.write_object_stream(blocks_pool, stream::iter(chunks)).await
+    }
+
+    /// Writes every item of `chunks` as a separate block followed by a
+    /// manifest block, returning the manifest id.
+    ///
+    /// If any write fails the chunks already written are deleted. A chunk
+    /// failed with [`crate::WriteBlockError::OutcomeUnknown`] may still be leaked.
+    pub async fn write_object_stream<S>(
+        &mut self,
+        blocks_pool: &BytesPool,
+        chunks: S,
+    )
+        -> Result<block::Id, WriteObjectError>
+    where S: Stream<Item = Bytes>,
+    {
+        futures::pin_mut!(chunks);
+        let mut manifest = Manifest {
+            object_size: 0,
+            chunks: Vec::new(),
+        };
+        while let Some(chunk_bytes) = chunks.next().await {
+            let chunk_size = chunk_bytes.len();
+            match self.write_block(chunk_bytes).await {
+                Ok(block_id) => {
+                    manifest.object_size += chunk_size as u64;
+                    manifest.chunks.push(block_id);
+                },
+                Err(error) => {
+                    self.delete_chunks(manifest.chunks).await;
+                    return Err(WriteObjectError::WriteBlock(error));
+                },
+            }
+        }
+
+        let mut manifest_bytes = blocks_pool.lend();
+        encode_manifest(&mut manifest_bytes, &manifest);
+        match self.write_block(manifest_bytes.freeze()).await {
+            Ok(manifest_block_id) =>
+                Ok(manifest_block_id),
+            Err(error) => {
+                self.delete_chunks(manifest.chunks).await;
+                Err(WriteObjectError::WriteBlock(error))
+            },
+        }
+    }
+
+    /// Reads the object identified by its manifest id and reassembles it
+    /// into a single buffer. The object size recorded in the manifest must
+    /// match the chunks read, otherwise the manifest is reported invalid.
+    pub async fn read_object(
+        &mut self,
+        blocks_pool: &BytesPool,
+        manifest_block_id: block::Id,
+    )
+        -> Result<Bytes, ReadObjectError>
+    {
+        let manifest = self.read_manifest(manifest_block_id).await?;
+        // the buffer grows with the chunks actually read rather than being
+        // sized upfront by the manifest
+        let mut object_bytes = blocks_pool.lend();
+        for chunk_block_id in manifest.chunks {
+            let chunk_bytes = self.read_block(chunk_block_id).await
+                .map_err(ReadObjectError::ReadBlock)?;
+            object_bytes.extend_from_slice(&chunk_bytes);
+        }
+        if object_bytes.len() as u64 != manifest.object_size {
+            return Err(ReadObjectError::InvalidManifest);
+        }
+        Ok(object_bytes.freeze())
+    }
+
+    /// Reads the object identified by its manifest id chunk by chunk.
+    pub async fn read_object_stream(
+        &mut self,
+        manifest_block_id: block::Id,
+    )
+        -> Result<BoxStream<'static, Result<Bytes, ReadObjectError>>, ReadObjectError>
+    {
+        let manifest = self.read_manifest(manifest_block_id).await?;
+        let chunks = stream::unfold(
+            (self.clone(), manifest.chunks.into_iter()),
+            |(mut pid, mut chunks)| async move {
+                let chunk_block_id = chunks.next()?;
+                let result = pid.read_block(chunk_block_id).await
+                    .map_err(ReadObjectError::ReadBlock);
+                Some((result, (pid, chunks)))
+            },
+        );
+        Ok(chunks.boxed())
+    }
+
+    /// Deletes all chunks of the object and then its manifest.
+    pub async fn delete_object(&mut self, manifest_block_id: block::Id) -> Result<Deleted, DeleteObjectError> {
+        let manifest = self.read_manifest(manifest_block_id.clone()).await
+            .map_err(DeleteObjectError::ReadManifest)?;
+        for chunk_block_id in manifest.chunks {
+            match self.delete_block(chunk_block_id).await {
+                Ok(Deleted) | Err(DeleteBlockError::NotFound) =>
+                    (),
+                Err(error) =>
+                    return Err(DeleteObjectError::DeleteBlock(error)),
+            }
+        }
+        self.delete_block(manifest_block_id).await
+            .map_err(DeleteObjectError::DeleteBlock)
+    }
+
+    async fn read_manifest(&mut self, manifest_block_id: block::Id) -> Result<Manifest, ReadObjectError> {
+        let manifest_bytes = self.read_block(manifest_block_id).await
+            .map_err(ReadObjectError::ReadBlock)?;
+        decode_manifest(&manifest_bytes)
+            .ok_or(ReadObjectError::InvalidManifest)
+    }
+
+    async fn delete_chunks(&mut self, chunks: Vec<block::Id>) {
+        for chunk_block_id in chunks {
+            if let Err(error) = self.delete_block(chunk_block_id.clone()).await {
+                log::warn!("failed to clean up object chunk {:?}: {:?}", chunk_block_id, error);
+            }
+        }
+    }
+}
+
+fn encode_manifest(block_bytes: &mut Vec<u8>, manifest: &Manifest) {
+    block_bytes.extend_from_slice(MANIFEST_MAGIC);
+    block_bytes.push(MANIFEST_VERSION);
+    let checksum_offset = block_bytes.len();
+    block_bytes.extend_from_slice(&[0; 4]);
+    bincode::serialize_into(&mut *block_bytes, manifest)
+        .expect("object manifest serialization into memory buffer failed");
+    let checksum = manifest_checksum(&block_bytes[.. checksum_offset], &block_bytes[MANIFEST_HEADER_SIZE ..]);
+    block_bytes[checksum_offset .. MANIFEST_HEADER_SIZE].copy_from_slice(&checksum.to_le_bytes());
+}
+
+fn decode_manifest(block_bytes: &[u8]) -> Option<Manifest> {
+    if block_bytes.len() < MANIFEST_HEADER_SIZE {
+        return None;
+    }
+    let (header, payload) = block_bytes.split_at(MANIFEST_HEADER_SIZE);
+    let (prefix, checksum_bytes) = header.split_at(MANIFEST_HEADER_SIZE - 4);
+    if !prefix.starts_with(&MANIFEST_MAGIC[..]) || prefix[MANIFEST_MAGIC.len()] != MANIFEST_VERSION {
+        return None;
+    }
+    let checksum = u32::from_le_bytes(checksum_bytes.try_into().ok()?);
+    if manifest_checksum(prefix, payload) != checksum {
+        return None;
+    }
+    bincode::deserialize(payload).ok()
+}
+
+fn manifest_checksum(prefix: &[u8], payload: &[u8]) -> u32 {
+    crc32c::crc32c_append(crc32c::crc32c(prefix), payload)
+}
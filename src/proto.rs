@@ -11,41 +11,78 @@ use alloc_pool::{
 };
 
 use crate::{
+    trace,
+    stats,
     block,
     Info,
     Flushed,
     Deleted,
     IterBlocks,
+    IterBlockIds,
+    Terminated,
+    ScrubReport,
+    WriteToken,
     RequestReadBlockError,
     RequestWriteBlockError,
     RequestDeleteBlockError,
 };
 
+/// Reply channel carrying the span of the originating `Pid` call, so the
+/// reply job in `ftd_sklave` is attributed to the same request.
+#[derive(Debug)]
+pub struct ReplyTx<T> {
+    reply_tx: oneshot::Sender<T>,
+    span: trace::Span,
+}
+
+impl<T> ReplyTx<T> {
+    pub fn new(reply_tx: oneshot::Sender<T>, span: trace::Span) -> Self {
+        ReplyTx { reply_tx, span, }
+    }
+
+    pub fn span(&self) -> &trace::Span {
+        &self.span
+    }
+
+    pub fn send(self, value: T) -> Result<(), T> {
+        self.span.event("sending reply");
+        self.reply_tx.send(value)
+    }
+}
+
 #[derive(Debug)]
 pub enum Request {
     Info(RequestInfo),
     Flush(RequestFlush),
     WriteBlock(RequestWriteBlock),
+    WriteBlockWithToken(RequestWriteBlockWithToken),
     ReadBlock(RequestReadBlock),
     DeleteBlock(RequestDeleteBlock),
     IterBlocks(RequestIterBlocks),
+    IterBlockIds(RequestIterBlockIds),
+    WriteBlocks(RequestWriteBlocks),
+    ReadBlocks(RequestReadBlocks),
+    DeleteBlocks(RequestDeleteBlocks),
+    Scrub(RequestScrub),
+    Stats(RequestStats),
+    Shutdown(RequestShutdown),
 }
 
-pub type RequestInfoReplyTx = oneshot::Sender<Info>;
+pub type RequestInfoReplyTx = ReplyTx<Info>;
 
 #[derive(Debug)]
 pub struct RequestInfo {
     pub reply_tx: RequestInfoReplyTx,
 }
 
-pub type RequestFlushReplyTx = oneshot::Sender<Flushed>;
+pub type RequestFlushReplyTx = ReplyTx<Flushed>;
 
 #[derive(Debug)]
 pub struct RequestFlush {
     pub reply_tx: RequestFlushReplyTx,
 }
 
-pub type RequestWriteBlockReplyTx = oneshot::Sender<Result<block::Id, RequestWriteBlockError>>;
+pub type RequestWriteBlockReplyTx = ReplyTx<Result<block::Id, RequestWriteBlockError>>;
 
 #[derive(Debug)]
 pub struct RequestWriteBlock {
@@ -53,7 +90,38 @@ pub struct RequestWriteBlock {
     pub reply_tx: RequestWriteBlockReplyTx,
 }
 
-pub type RequestReadBlockReplyTx = oneshot::Sender<Result<Bytes, RequestReadBlockError>>;
+#[derive(Clone, Debug)]
+pub enum WriteBlockWithTokenError {
+    NoSpaceLeft,
+    OutcomeUnknown,
+}
+
+pub type RequestWriteBlockWithTokenReplyTx = ReplyTx<Result<block::Id, WriteBlockWithTokenError>>;
+
+#[derive(Debug)]
+pub struct RequestWriteBlockWithToken {
+    pub write_token: WriteToken,
+    pub block_bytes: Bytes,
+    pub reply_tx: RequestWriteBlockWithTokenReplyTx,
+}
+
+#[derive(Clone, Debug)]
+pub enum ReadBlockError {
+    NotFound,
+    /// The block checksum does not match its contents.
+    Corrupted,
+    DecryptionFailed,
+    /// The block frame names an unknown codec or fails to decompress.
+    Undecodable,
+    /// The decoding job could not run on the thread pool.
+    DecodingFailed,
+}
+
+pub type RequestReadBlockReplyTx = ReplyTx<Result<Bytes, ReadBlockError>>;
+
+/// Reply of the meister read, the block is decoded by the gen server before
+/// it is sent on with `RequestReadBlockReplyTx`.
+pub type MeisterReadBlockReplyTx = ReplyTx<Result<Bytes, RequestReadBlockError>>;
 
 #[derive(Debug)]
 pub struct RequestReadBlock {
@@ -61,7 +129,7 @@ pub struct RequestReadBlock {
     pub reply_tx: RequestReadBlockReplyTx,
 }
 
-pub type RequestDeleteBlockReplyTx = oneshot::Sender<Result<Deleted, RequestDeleteBlockError>>;
+pub type RequestDeleteBlockReplyTx = ReplyTx<Result<Deleted, RequestDeleteBlockError>>;
 
 #[derive(Debug)]
 pub struct RequestDeleteBlock {
@@ -69,9 +137,68 @@ pub struct RequestDeleteBlock {
     pub reply_tx: RequestDeleteBlockReplyTx,
 }
 
-pub type RequestIterBlocksReplyTx = oneshot::Sender<IterBlocks>;
+pub type RequestIterBlocksReplyTx = ReplyTx<IterBlocks>;
 
 #[derive(Debug)]
 pub struct RequestIterBlocks {
+    /// Where to resume a previous iteration, `None` to start from scratch.
+    pub iterator_next: Option<blockwheel_fs::IterBlocksIterator>,
     pub reply_tx: RequestIterBlocksReplyTx,
 }
+
+pub type RequestIterBlockIdsReplyTx = ReplyTx<IterBlockIds>;
+
+#[derive(Debug)]
+pub struct RequestIterBlockIds {
+    pub reply_tx: RequestIterBlockIdsReplyTx,
+}
+
+/// Batch replies keep the order of the request items, `None` marks an item
+/// whose reply has been canceled and which should be resubmitted.
+pub type RequestWriteBlocksReplyTx = ReplyTx<Vec<Option<Result<block::Id, RequestWriteBlockError>>>>;
+
+#[derive(Debug)]
+pub struct RequestWriteBlocks {
+    pub blocks_bytes: Vec<Bytes>,
+    pub reply_tx: RequestWriteBlocksReplyTx,
+}
+
+pub type RequestReadBlocksReplyTx = ReplyTx<Vec<Option<Result<Bytes, ReadBlockError>>>>;
+
+#[derive(Debug)]
+pub struct RequestReadBlocks {
+    pub block_ids: Vec<block::Id>,
+    pub reply_tx: RequestReadBlocksReplyTx,
+}
+
+pub type RequestDeleteBlocksReplyTx = ReplyTx<Vec<Option<Result<Deleted, RequestDeleteBlockError>>>>;
+
+#[derive(Debug)]
+pub struct RequestDeleteBlocks {
+    pub block_ids: Vec<block::Id>,
+    pub reply_tx: RequestDeleteBlocksReplyTx,
+}
+
+pub type RequestScrubReplyTx = ReplyTx<ScrubReport>;
+
+#[derive(Debug)]
+pub struct RequestScrub {
+    pub reply_tx: RequestScrubReplyTx,
+}
+
+pub type RequestStatsReplyTx = ReplyTx<stats::Stats>;
+
+#[derive(Debug)]
+pub struct RequestStats {
+    pub reply_tx: RequestStatsReplyTx,
+}
+
+pub type RequestShutdownReplyTx = ReplyTx<Terminated>;
+
+#[derive(Debug)]
+pub struct RequestShutdown {
+    /// Until when blocks iterators still running are waited for, `None`
+    /// waits for them to finish however long it takes.
+    pub iterators_deadline: Option<tokio::time::Instant>,
+    pub reply_tx: RequestShutdownReplyTx,
+}
@@ -0,0 +1,236 @@
+use std::{
+    cmp,
+    time::{
+        Instant,
+        Duration,
+    },
+    collections::{
+        VecDeque,
+    },
+};
+
+/// What the gen server does when its blockwheel-fs meister fails.
+///
+/// On restart the wheel is reopened with the same `Params`, while the request
+/// channel is kept, so existing `Pid` clones keep working.
+#[derive(Clone, Debug, Default)]
+pub enum RestartPolicy {
+    /// Terminate on the first error.
+    #[default]
+    InstantCrash,
+    /// Reopen the wheel immediately.
+    InstantRestart {
+        limit: Option<RestartLimit>,
+    },
+    /// Reopen the wheel after a delay growing with each consecutive restart.
+    ///
+    /// Restarts count as consecutive while the server keeps failing within
+    /// `backoff.max_delay` after being reopened: a server which stays up for
+    /// longer than that is considered recovered and the next restart waits
+    /// `backoff.initial_delay` again.
+    DelayedRestart {
+        backoff: Backoff,
+        limit: Option<RestartLimit>,
+    },
+}
+
+/// Exponential backoff between restarts.
+///
+/// Each consecutive restart waits `multiplier` times longer than the previous
+/// one, capped at `max_delay`. The delay is reset back to `initial_delay`
+/// once the server stays up for at least `max_delay`.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: u32,
+}
+
+/// Gives up restarting once `max_restarts` happened within `window`.
+#[derive(Clone, Debug)]
+pub struct RestartLimit {
+    pub max_restarts: usize,
+    pub window: Duration,
+}
+
+pub(crate) enum Decision {
+    Crash,
+    RestartAfter(Duration),
+}
+
+pub(crate) struct Tracker {
+    policy: RestartPolicy,
+    restarts: VecDeque<Instant>,
+    current_delay: Option<Duration>,
+}
+
+impl Tracker {
+    pub(crate) fn new(policy: RestartPolicy) -> Tracker {
+        Tracker {
+            policy,
+            restarts: VecDeque::new(),
+            current_delay: None,
+        }
+    }
+
+    /// Decides what to do with a server which failed after running for
+    /// `uptime`.
+    pub(crate) fn decide(&mut self, uptime: Duration) -> Decision {
+        self.decide_at(Instant::now(), uptime)
+    }
+
+    fn decide_at(&mut self, now: Instant, uptime: Duration) -> Decision {
+        let limit = match self.policy {
+            RestartPolicy::InstantCrash =>
+                return Decision::Crash,
+            RestartPolicy::InstantRestart { ref limit, } |
+            RestartPolicy::DelayedRestart { ref limit, .. } =>
+                limit,
+        };
+
+        if let Some(limit) = limit {
+            while let Some(restarted_at) = self.restarts.front() {
+                if now.duration_since(*restarted_at) <= limit.window {
+                    break;
+                }
+                self.restarts.pop_front();
+            }
+            if self.restarts.len() >= limit.max_restarts {
+                return Decision::Crash;
+            }
+            self.restarts.push_back(now);
+        }
+
+        match self.policy {
+            RestartPolicy::InstantCrash =>
+                Decision::Crash,
+            RestartPolicy::InstantRestart { .. } =>
+                Decision::RestartAfter(Duration::ZERO),
+            RestartPolicy::DelayedRestart { ref backoff, .. } => {
+                let delay = match self.current_delay {
+                    Some(current_delay) if uptime < backoff.max_delay =>
+                        cmp::min(
+                            current_delay.saturating_mul(backoff.multiplier),
+                            backoff.max_delay,
+                        ),
+                    _ =>
+                        backoff.initial_delay,
+                };
+                self.current_delay = Some(delay);
+                Decision::RestartAfter(delay)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        time::{
+            Instant,
+            Duration,
+        },
+    };
+
+    use super::{
+        Backoff,
+        Decision,
+        Tracker,
+        RestartLimit,
+        RestartPolicy,
+    };
+
+    const SHORT_UPTIME: Duration = Duration::from_millis(1);
+
+    fn delay(decision: Decision) -> Option<Duration> {
+        match decision {
+            Decision::Crash =>
+                None,
+            Decision::RestartAfter(delay) =>
+                Some(delay),
+        }
+    }
+
+    fn backoff() -> Backoff {
+        Backoff {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            multiplier: 2,
+        }
+    }
+
+    #[test]
+    fn instant_crash_never_restarts() {
+        let mut tracker = Tracker::new(RestartPolicy::InstantCrash);
+        assert_eq!(delay(tracker.decide(SHORT_UPTIME)), None);
+        assert_eq!(delay(tracker.decide(Duration::from_secs(3600))), None);
+    }
+
+    #[test]
+    fn instant_restart_without_limit() {
+        let mut tracker = Tracker::new(RestartPolicy::InstantRestart { limit: None, });
+        for _ in 0 .. 1000 {
+            assert_eq!(delay(tracker.decide(SHORT_UPTIME)), Some(Duration::ZERO));
+        }
+    }
+
+    #[test]
+    fn limit_counts_restarts_within_window() {
+        let mut tracker = Tracker::new(RestartPolicy::InstantRestart {
+            limit: Some(RestartLimit { max_restarts: 3, window: Duration::from_secs(10), }),
+        });
+        let now = Instant::now();
+        for offset in 0 .. 3 {
+            let decision = tracker.decide_at(now + Duration::from_secs(offset), SHORT_UPTIME);
+            assert_eq!(delay(decision), Some(Duration::ZERO));
+        }
+        assert_eq!(delay(tracker.decide_at(now + Duration::from_secs(3), SHORT_UPTIME)), None);
+    }
+
+    #[test]
+    fn restarts_outside_window_are_forgotten() {
+        let mut tracker = Tracker::new(RestartPolicy::InstantRestart {
+            limit: Some(RestartLimit { max_restarts: 2, window: Duration::from_secs(10), }),
+        });
+        let now = Instant::now();
+        assert_eq!(delay(tracker.decide_at(now, SHORT_UPTIME)), Some(Duration::ZERO));
+        assert_eq!(delay(tracker.decide_at(now + Duration::from_secs(5), SHORT_UPTIME)), Some(Duration::ZERO));
+        // the first restart left the window, the second one is still in it
+        assert_eq!(delay(tracker.decide_at(now + Duration::from_secs(11), SHORT_UPTIME)), Some(Duration::ZERO));
+        assert_eq!(delay(tracker.decide_at(now + Duration::from_secs(12), SHORT_UPTIME)), None);
+    }
+
+    #[test]
+    fn backoff_grows_up_to_max_delay() {
+        let mut tracker = Tracker::new(RestartPolicy::DelayedRestart { backoff: backoff(), limit: None, });
+        let delays: Vec<_> = (0 .. 6)
+            .map(|_| delay(tracker.decide(SHORT_UPTIME)).unwrap())
+            .collect();
+        assert_eq!(
+            delays,
+            [100, 200, 400, 800, 1000, 1000].map(Duration::from_millis).to_vec(),
+        );
+    }
+
+    #[test]
+    fn backoff_resets_after_long_uptime() {
+        let mut tracker = Tracker::new(RestartPolicy::DelayedRestart { backoff: backoff(), limit: None, });
+        assert_eq!(delay(tracker.decide(SHORT_UPTIME)), Some(Duration::from_millis(100)));
+        assert_eq!(delay(tracker.decide(SHORT_UPTIME)), Some(Duration::from_millis(200)));
+        // just below max delay: still consecutive
+        assert_eq!(delay(tracker.decide(Duration::from_millis(999))), Some(Duration::from_millis(400)));
+        assert_eq!(delay(tracker.decide(Duration::from_secs(1))), Some(Duration::from_millis(100)));
+        assert_eq!(delay(tracker.decide(SHORT_UPTIME)), Some(Duration::from_millis(200)));
+    }
+
+    #[test]
+    fn delayed_restart_respects_limit() {
+        let mut tracker = Tracker::new(RestartPolicy::DelayedRestart {
+            backoff: backoff(),
+            limit: Some(RestartLimit { max_restarts: 1, window: Duration::from_secs(60), }),
+        });
+        let now = Instant::now();
+        assert_eq!(delay(tracker.decide_at(now, SHORT_UPTIME)), Some(Duration::from_millis(100)));
+        assert_eq!(delay(tracker.decide_at(now + Duration::from_secs(1), SHORT_UPTIME)), None);
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    sync::{
+        atomic::{
+            Ordering,
+            AtomicUsize,
+        },
+        Arc,
+    },
+};
+
+use futures::{
+    future,
+    stream::{
+        self,
+        BoxStream,
+    },
+    StreamExt,
+};
+
+use serde::{
+    Serialize,
+    Deserialize,
+};
+
+use alloc_pool::{
+    bytes::{
+        Bytes,
+    },
+};
+
+use crate::{
+    block,
+    Pid,
+    Info,
+    Flushed,
+    Deleted,
+    InfoError,
+    FlushError,
+    ReadBlockError,
+    WriteBlockError,
+    ShardingError,
+    IterBlocksError,
+    DeleteBlockError,
+};
+
+/// How [`ShardedPid::write_block`] picks a shard for a new block.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShardingStrategy {
+    /// Cycle through the shards.
+    RoundRobin,
+    /// Pick the shard reporting the most free space.
+    ///
+    /// The free space is not cached: every write first asks all shards for
+    /// their info, so a write costs one extra request per shard.
+    MostFreeSpace,
+}
+
+/// Block id qualified with the index of the shard holding the block.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub struct ShardedBlockId {
+    pub shard: usize,
+    pub block_id: block::Id,
+}
+
+/// Frontend spreading blocks over several wheels, one `Pid` per shard.
+///
+/// The order of `shards` is part of the block ids handed out, so it must be
+/// kept the same across restarts.
+#[derive(Clone)]
+pub struct ShardedPid {
+    shards: Vec<Pid>,
+    strategy: ShardingStrategy,
+    next_shard: Arc<AtomicUsize>,
+}
+
+pub struct ShardedIterBlocks {
+    pub blocks_total_count: usize,
+    pub blocks_total_size: usize,
+    pub blocks: BoxStream<'static, Result<(ShardedBlockId, Bytes), IterBlocksError>>,
+}
+
+impl ShardedPid {
+    pub fn new(shards: Vec<Pid>, strategy: ShardingStrategy) -> Result<ShardedPid, ShardingError> {
+        if shards.is_empty() {
+            return Err(ShardingError::NoShards);
+        }
+        Ok(ShardedPid {
+            shards,
+            strategy,
+            next_shard: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    pub fn shards_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub async fn info(&mut self) -> Result<Vec<Info>, InfoError> {
+        future::try_join_all(self.shards.iter_mut().map(|pid| pid.info())).await
+    }
+
+    pub async fn flush(&mut self) -> Result<Flushed, FlushError> {
+        future::try_join_all(self.shards.iter_mut().map(|pid| pid.flush())).await?;
+        Ok(Flushed)
+    }
+
+    /// Writes a block into the shard chosen by the strategy, spilling over
+    /// to the other shards if it has no space left.
+    pub async fn write_block(&mut self, block_bytes: Bytes) -> Result<ShardedBlockId, WriteBlockError> {
+        for shard in self.shards_order().await {
+            match self.shards[shard].write_block(block_bytes.clone()).await {
+                Ok(block_id) =>
+                    return Ok(ShardedBlockId { shard, block_id, }),
+                Err(WriteBlockError::NoSpaceLeft) =>
+                    (),
+                Err(error) =>
+                    return Err(error),
+            }
+        }
+        Err(WriteBlockError::NoSpaceLeft)
+    }
+
+    pub async fn read_block(&mut self, block_id: ShardedBlockId) -> Result<Bytes, ReadBlockError> {
+        match self.shards.get_mut(block_id.shard) {
+            None =>
+                Err(ReadBlockError::NotFound),
+            Some(pid) =>
+                pid.read_block(block_id.block_id).await,
+        }
+    }
+
+    pub async fn delete_block(&mut self, block_id: ShardedBlockId) -> Result<Deleted, DeleteBlockError> {
+        match self.shards.get_mut(block_id.shard) {
+            None =>
+                Err(DeleteBlockError::NotFound),
+            Some(pid) =>
+                pid.delete_block(block_id.block_id).await,
+        }
+    }
+
+    /// Iterates over blocks of all shards, interleaving the shard streams.
+    pub async fn iter_blocks(&mut self) -> Result<ShardedIterBlocks, IterBlocksError> {
+        let shards_iter_blocks =
+            future::try_join_all(self.shards.iter_mut().map(|pid| pid.iter_blocks())).await?;
+
+        let mut blocks_total_count = 0;
+        let mut blocks_total_size = 0;
+        let mut shards_streams = Vec::with_capacity(shards_iter_blocks.len());
+        for (shard, iter_blocks) in shards_iter_blocks.into_iter().enumerate() {
+            blocks_total_count += iter_blocks.blocks_total_count;
+            blocks_total_size += iter_blocks.blocks_total_size;
+            shards_streams.push(
+                iter_blocks
+                    .into_stream()
+                    .map(move |item| item.map(|(block_id, block_bytes)| {
+                        (ShardedBlockId { shard, block_id, }, block_bytes)
+                    })),
+            );
+        }
+
+        Ok(ShardedIterBlocks {
+            blocks_total_count,
+            blocks_total_size,
+            blocks: stream::select_all(shards_streams).boxed(),
+        })
+    }
+
+    async fn shards_order(&mut self) -> Vec<usize> {
+        let shards_count = self.shards.len();
+        let start = self.next_shard.fetch_add(1, Ordering::Relaxed) % shards_count;
+        let mut order: Vec<usize> = (0 .. shards_count)
+            .map(|offset| (start + offset) % shards_count)
+            .collect();
+
+        if let ShardingStrategy::MostFreeSpace = self.strategy {
+            let infos = future::join_all(self.shards.iter_mut().map(|pid| pid.info())).await;
+            // shards which failed to report come last, ties keep round robin order
+            order.sort_by_key(|&shard| match &infos[shard] {
+                Ok(info) =>
+                    (0, usize::MAX - info.bytes_free),
+                Err(..) =>
+                    (1, 0),
+            });
+        }
+
+        order
+    }
+}
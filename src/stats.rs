@@ -0,0 +1,256 @@
+use std::{
+    sync::{
+        atomic::{
+            Ordering,
+            AtomicU64,
+            AtomicUsize,
+        },
+    },
+    time::{
+        Duration,
+    },
+};
+
+use crate::{
+    InfoError,
+    FlushError,
+    ReadBlockError,
+    WriteBlockError,
+    IterBlocksError,
+    DeleteBlockError,
+};
+
+/// Number of latency histogram buckets: bucket `i` counts requests completed
+/// in less than `2^i` microseconds, the last one also counts everything slower.
+pub const LATENCY_BUCKETS: usize = 32;
+
+/// Snapshot of the gen server counters, returned by [`crate::Pid::stats`].
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    pub info: OpStats,
+    pub flush: OpStats,
+    pub write_block: OpStats,
+    pub read_block: OpStats,
+    pub delete_block: OpStats,
+    pub iter_blocks: OpStats,
+    pub bytes_written: u64,
+    pub bytes_read: u64,
+    pub active_iterators: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct OpStats {
+    /// Requests dispatched by the gen server, resubmissions included.
+    pub requests: u64,
+    pub successes: u64,
+    pub no_space_left: u64,
+    pub not_found: u64,
+    /// Requests failed because of a timeout or a lost gen server.
+    pub failures: u64,
+    pub latency: LatencyHistogram,
+}
+
+#[derive(Clone, Debug)]
+pub struct LatencyHistogram {
+    pub buckets: [u64; LATENCY_BUCKETS],
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> LatencyHistogram {
+        LatencyHistogram {
+            buckets: [0; LATENCY_BUCKETS],
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Upper bound of the bucket containing the `q` quantile (`0.0 ..= 1.0`),
+    /// `None` if nothing has been recorded yet.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((count as f64) * q.clamp(0.0, 1.0)).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket;
+            if seen >= rank {
+                return Some(Duration::from_micros(1 << index));
+            }
+        }
+        Some(Duration::from_micros(1 << (LATENCY_BUCKETS - 1)))
+    }
+}
+
+pub(crate) enum Outcome {
+    Success,
+    NoSpaceLeft,
+    NotFound,
+    Failure,
+}
+
+pub(crate) trait Classify {
+    fn outcome(&self) -> Outcome;
+}
+
+impl<T, E> Classify for Result<T, E> where E: Classify {
+    fn outcome(&self) -> Outcome {
+        match self {
+            Ok(..) =>
+                Outcome::Success,
+            Err(error) =>
+                error.outcome(),
+        }
+    }
+}
+
+impl Classify for InfoError {
+    fn outcome(&self) -> Outcome {
+        Outcome::Failure
+    }
+}
+
+impl Classify for FlushError {
+    fn outcome(&self) -> Outcome {
+        Outcome::Failure
+    }
+}
+
+impl Classify for WriteBlockError {
+    fn outcome(&self) -> Outcome {
+        match self {
+            WriteBlockError::NoSpaceLeft =>
+                Outcome::NoSpaceLeft,
+            _ =>
+                Outcome::Failure,
+        }
+    }
+}
+
+impl Classify for ReadBlockError {
+    fn outcome(&self) -> Outcome {
+        match self {
+            ReadBlockError::NotFound =>
+                Outcome::NotFound,
+            _ =>
+                Outcome::Failure,
+        }
+    }
+}
+
+impl Classify for DeleteBlockError {
+    fn outcome(&self) -> Outcome {
+        match self {
+            DeleteBlockError::NotFound =>
+                Outcome::NotFound,
+            _ =>
+                Outcome::Failure,
+        }
+    }
+}
+
+impl Classify for IterBlocksError {
+    fn outcome(&self) -> Outcome {
+        Outcome::Failure
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Counters {
+    pub(crate) info: OpCounters,
+    pub(crate) flush: OpCounters,
+    pub(crate) write_block: OpCounters,
+    pub(crate) read_block: OpCounters,
+    pub(crate) delete_block: OpCounters,
+    pub(crate) iter_blocks: OpCounters,
+    pub(crate) bytes_written: AtomicU64,
+    pub(crate) bytes_read: AtomicU64,
+    pub(crate) active_iterators: AtomicUsize,
+}
+
+impl Counters {
+    pub(crate) fn snapshot(&self) -> Stats {
+        Stats {
+            info: self.info.snapshot(),
+            flush: self.flush.snapshot(),
+            write_block: self.write_block.snapshot(),
+            read_block: self.read_block.snapshot(),
+            delete_block: self.delete_block.snapshot(),
+            iter_blocks: self.iter_blocks.snapshot(),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            active_iterators: self.active_iterators.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub(crate) struct OpCounters {
+    requests: AtomicU64,
+    successes: AtomicU64,
+    no_space_left: AtomicU64,
+    not_found: AtomicU64,
+    failures: AtomicU64,
+    latency: [AtomicU64; LATENCY_BUCKETS],
+}
+
+impl Default for OpCounters {
+    fn default() -> OpCounters {
+        OpCounters {
+            requests: AtomicU64::new(0),
+            successes: AtomicU64::new(0),
+            no_space_left: AtomicU64::new(0),
+            not_found: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            latency: std::array::from_fn(|_| AtomicU64::new(0)),
+        }
+    }
+}
+
+impl OpCounters {
+    pub(crate) fn request(&self, count: usize) {
+        self.requests.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record<R>(&self, result: &R, elapsed: Duration) where R: Classify {
+        let counter = match result.outcome() {
+            Outcome::Success =>
+                &self.successes,
+            Outcome::NoSpaceLeft =>
+                &self.no_space_left,
+            Outcome::NotFound =>
+                &self.not_found,
+            Outcome::Failure =>
+                &self.failures,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        let micros = elapsed.as_micros();
+        let bucket = if micros == 0 {
+            0
+        } else {
+            (u128::BITS - micros.leading_zeros()) as usize
+        };
+        self.latency[bucket.min(LATENCY_BUCKETS - 1)]
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> OpStats {
+        let mut latency = LatencyHistogram::default();
+        for (bucket, counter) in latency.buckets.iter_mut().zip(self.latency.iter()) {
+            *bucket = counter.load(Ordering::Relaxed);
+        }
+        OpStats {
+            requests: self.requests.load(Ordering::Relaxed),
+            successes: self.successes.load(Ordering::Relaxed),
+            no_space_left: self.no_space_left.load(Ordering::Relaxed),
+            not_found: self.not_found.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            latency,
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Request spans which are compiled away unless the `tracing` feature is on.
+
+use crate::{
+    block,
+    stats::{
+        Classify,
+    },
+};
+
+#[cfg(feature = "tracing")]
+use crate::stats::Outcome;
+
+#[cfg(feature = "tracing")]
+#[derive(Clone, Debug)]
+pub(crate) struct Span(tracing::Span);
+
+#[cfg(not(feature = "tracing"))]
+#[derive(Clone, Debug)]
+pub(crate) struct Span;
+
+#[cfg(feature = "tracing")]
+impl Span {
+    pub(crate) fn request(op: &'static str) -> Span {
+        Span(tracing::debug_span!(
+            "blockwheel_fs_ero",
+            op,
+            block_id = tracing::field::Empty,
+            block_size = tracing::field::Empty,
+            outcome = tracing::field::Empty,
+        ))
+    }
+
+    pub(crate) fn none() -> Span {
+        Span(tracing::Span::none())
+    }
+
+    pub(crate) fn event(&self, stage: &'static str) {
+        let _entered = self.0.enter();
+        tracing::debug!(stage);
+    }
+
+    pub(crate) fn record_block_id(&self, block_id: &block::Id) {
+        self.0.record("block_id", tracing::field::debug(block_id));
+    }
+
+    pub(crate) fn record_block_size(&self, block_size: usize) {
+        self.0.record("block_size", block_size);
+    }
+
+    pub(crate) fn record_outcome<R>(&self, result: &R) where R: Classify {
+        self.0.record("outcome", outcome_str(result.outcome()));
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    pub(crate) fn request(_op: &'static str) -> Span {
+        Span
+    }
+
+    pub(crate) fn none() -> Span {
+        Span
+    }
+
+    pub(crate) fn event(&self, _stage: &'static str) {
+    }
+
+    pub(crate) fn record_block_id(&self, _block_id: &block::Id) {
+    }
+
+    pub(crate) fn record_block_size(&self, _block_size: usize) {
+    }
+
+    pub(crate) fn record_outcome<R>(&self, _result: &R) where R: Classify {
+    }
+}
+
+#[cfg(feature = "tracing")]
+fn outcome_str(outcome: Outcome) -> &'static str {
+    match outcome {
+        Outcome::Success =>
+            "success",
+        Outcome::NoSpaceLeft =>
+            "no_space_left",
+        Outcome::NotFound =>
+            "not_found",
+        Outcome::Failure =>
+            "failure",
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    collections::{
+        HashMap,
+        VecDeque,
+    },
+};
+
+use futures::{
+    channel::{
+        oneshot,
+    },
+    future::{
+        BoxFuture,
+    },
+    stream::{
+        FuturesUnordered,
+    },
+    FutureExt,
+};
+
+use crate::{
+    proto,
+    block,
+    WriteToken,
+    RequestWriteBlockError,
+};
+
+/// How many settled write tokens are remembered for deduplication.
+const SETTLED_CAPACITY: usize = 65536;
+
+pub type WriteResult = Result<Result<block::Id, RequestWriteBlockError>, oneshot::Canceled>;
+
+/// Deduplication state of client supplied write tokens.
+///
+/// It is kept across gen server restarts: a write which was in flight when
+/// the meister has been lost is remembered as having an unknown outcome.
+#[derive(Default)]
+pub struct WriteTokens {
+    entries: HashMap<WriteToken, Entry>,
+    settled: VecDeque<WriteToken>,
+    pub pending: FuturesUnordered<BoxFuture<'static, (WriteToken, WriteResult)>>,
+}
+
+enum Entry {
+    InFlight { waiters: Vec<proto::RequestWriteBlockWithTokenReplyTx>, },
+    Written { block_id: block::Id, },
+    OutcomeUnknown,
+}
+
+impl WriteTokens {
+    /// Returns `true` if the write for `write_token` has to be dispatched to
+    /// the meister, otherwise `reply_tx` is either answered right away or
+    /// attached to the write already in flight.
+    pub fn register(&mut self, write_token: WriteToken, reply_tx: proto::RequestWriteBlockWithTokenReplyTx) -> bool {
+        let reply = match self.entries.get_mut(&write_token) {
+            None => {
+                self.entries.insert(write_token, Entry::InFlight { waiters: vec![reply_tx], });
+                return true;
+            },
+            Some(Entry::InFlight { waiters, }) => {
+                waiters.push(reply_tx);
+                return false;
+            },
+            Some(Entry::Written { block_id, }) =>
+                Ok(block_id.clone()),
+            Some(Entry::OutcomeUnknown) =>
+                Err(proto::WriteBlockWithTokenError::OutcomeUnknown),
+        };
+        if let Err(_send_error) = reply_tx.send(reply) {
+            log::debug!("client is gone during RequestWriteBlockWithToken");
+        }
+        false
+    }
+
+    pub fn track(&mut self, write_token: WriteToken, write_rx: oneshot::Receiver<Result<block::Id, RequestWriteBlockError>>) {
+        self.pending.push(
+            write_rx
+                .map(move |write_result| (write_token, write_result))
+                .boxed(),
+        );
+    }
+
+    pub fn complete(&mut self, write_token: WriteToken, write_result: WriteResult) {
+        let waiters = match self.entries.remove(&write_token) {
+            Some(Entry::InFlight { waiters, }) =>
+                waiters,
+            _ =>
+                Vec::new(),
+        };
+        let reply = match write_result {
+            Ok(Ok(block_id)) => {
+                self.settle(write_token, Entry::Written { block_id: block_id.clone(), });
+                Ok(block_id)
+            },
+            Ok(Err(RequestWriteBlockError::NoSpaceLeft)) =>
+                Err(proto::WriteBlockWithTokenError::NoSpaceLeft),
+            Err(oneshot::Canceled) => {
+                log::warn!("write for token {:?} has been lost in flight, its outcome is unknown", write_token);
+                self.settle(write_token, Entry::OutcomeUnknown);
+                Err(proto::WriteBlockWithTokenError::OutcomeUnknown)
+            },
+        };
+        for reply_tx in waiters {
+            if let Err(_send_error) = reply_tx.send(reply.clone()) {
+                log::debug!("client is gone during RequestWriteBlockWithToken");
+            }
+        }
+    }
+
+    fn settle(&mut self, write_token: WriteToken, entry: Entry) {
+        self.entries.insert(write_token, entry);
+        self.settled.push_back(write_token);
+        while self.settled.len() > SETTLED_CAPACITY {
+            if let Some(evicted_token) = self.settled.pop_front() {
+                if let Some(Entry::InFlight { .. }) = self.entries.get(&evicted_token) {
+                    continue;
+                }
+                self.entries.remove(&evicted_token);
+            }
+        }
+    }
+}
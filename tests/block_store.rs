@@ -0,0 +1,151 @@
+use futures::{
+    StreamExt,
+};
+
+use alloc_pool::{
+    bytes::{
+        Bytes,
+    },
+};
+
+use blockwheel_fs_ero::{
+    block,
+    Deleted,
+    BlockStore,
+    FakeBlockStore,
+    IterBlocksItem,
+    ReadBlockError,
+    WriteBlockError,
+    DeleteBlockError,
+};
+
+mod common;
+
+use common::{
+    Env,
+    ram_params,
+};
+
+const WHEEL_SIZE_BYTES: usize = 4 * 1024 * 1024;
+
+/// Drains a blocks iteration, returning the blocks contents in iteration
+/// order and the cursor of the first block.
+async fn drain<S>(store: &mut S, from: Option<S::Cursor>) -> (Vec<Vec<u8>>, Option<S::Cursor>)
+where S: BlockStore,
+{
+    let iter_blocks = match from {
+        None =>
+            store.iter_blocks().await.unwrap(),
+        Some(cursor) =>
+            store.iter_blocks_from(cursor).await.unwrap(),
+    };
+    let mut blocks_rx = iter_blocks.blocks_rx;
+    let mut blocks = Vec::new();
+    let mut first_cursor = None;
+    loop {
+        match blocks_rx.next().await.unwrap() {
+            IterBlocksItem::Block { block_bytes, cursor, .. } => {
+                blocks.push(block_bytes.to_vec());
+                if first_cursor.is_none() {
+                    first_cursor = Some(cursor);
+                }
+            },
+            IterBlocksItem::Corrupted { block_id, .. } =>
+                panic!("unexpected corrupted block {:?}", block_id),
+            IterBlocksItem::NoMoreBlocks =>
+                return (blocks, first_cursor),
+        }
+    }
+}
+
+/// Writes `blocks`, deletes the second one, then iterates over the store and
+/// resumes the iteration after its first block.
+async fn write_and_iterate<S>(store: &mut S, blocks: &[Bytes]) -> (Vec<Vec<u8>>, Vec<Vec<u8>>)
+where S: BlockStore,
+{
+    let mut block_ids = Vec::new();
+    for block_bytes in blocks {
+        block_ids.push(store.write_block(block_bytes.clone()).await.unwrap());
+    }
+    store.delete_block(block_ids[1].clone()).await.unwrap();
+    let (all_blocks, first_cursor) = drain(store, None).await;
+    let (resumed_blocks, _) = drain(store, first_cursor).await;
+    (all_blocks, resumed_blocks)
+}
+
+#[tokio::test]
+async fn fake_write_read_delete() {
+    let env = Env::new();
+    let mut store = FakeBlockStore::new(WHEEL_SIZE_BYTES);
+
+    let block_bytes = env.block(0, 1024);
+    let block_id = store.write_block(block_bytes.clone()).await.unwrap();
+    assert_eq!(store.read_block(block_id.clone()).await.unwrap().to_vec(), block_bytes.to_vec());
+    assert!(matches!(store.delete_block(block_id.clone()).await, Ok(Deleted)));
+    assert!(matches!(store.read_block(block_id.clone()).await, Err(ReadBlockError::NotFound)));
+    assert!(matches!(store.delete_block(block_id).await, Err(DeleteBlockError::NotFound)));
+    assert!(matches!(store.read_block(block::Id::init()).await, Err(ReadBlockError::NotFound)));
+}
+
+#[tokio::test]
+async fn fake_no_space_left_accounting() {
+    let env = Env::new();
+    let mut store = FakeBlockStore::new(1024);
+
+    let block_id = store.write_block(env.block(0, 600)).await.unwrap();
+    assert!(matches!(store.write_block(env.block(1, 500)).await, Err(WriteBlockError::NoSpaceLeft)));
+    assert_eq!(store.bytes_used(), 600);
+    assert_eq!(store.blocks_count(), 1);
+
+    // a block filling the rest exactly still fits
+    store.write_block(env.block(2, 424)).await.unwrap();
+    assert_eq!(store.bytes_used(), 1024);
+
+    store.delete_block(block_id).await.unwrap();
+    assert_eq!(store.bytes_used(), 424);
+    store.write_block(env.block(1, 500)).await.unwrap();
+    assert_eq!(store.blocks_count(), 2);
+}
+
+#[tokio::test]
+async fn fake_iteration_order() {
+    let env = Env::new();
+    let mut store = FakeBlockStore::new(WHEEL_SIZE_BYTES);
+
+    let blocks: Vec<_> = (0 .. 8).map(|seed| env.block(seed, 256)).collect();
+    let mut block_ids = Vec::new();
+    for block_bytes in &blocks {
+        block_ids.push(store.write_block(block_bytes.clone()).await.unwrap());
+    }
+    let mut sorted_block_ids = block_ids.clone();
+    sorted_block_ids.sort();
+    assert_eq!(block_ids, sorted_block_ids);
+
+    let iter_blocks = store.iter_blocks().await.unwrap();
+    assert_eq!(iter_blocks.blocks_total_count, blocks.len());
+    assert_eq!(iter_blocks.blocks_total_size, blocks.len() * 256);
+    let iterated: Vec<_> = iter_blocks.into_stream()
+        .map(|item| item.unwrap())
+        .collect()
+        .await;
+    assert_eq!(
+        iterated.iter().map(|(block_id, _)| block_id.clone()).collect::<Vec<_>>(),
+        block_ids,
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fake_ordering_matches_gen_server() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+    let mut store = FakeBlockStore::new(WHEEL_SIZE_BYTES);
+
+    let blocks: Vec<_> = (0 .. 8).map(|seed| env.block(seed, 1024)).collect();
+    let (pid_blocks, pid_resumed) = write_and_iterate(&mut pid, &blocks).await;
+    let (fake_blocks, fake_resumed) = write_and_iterate(&mut store, &blocks).await;
+
+    assert_eq!(pid_blocks.len(), blocks.len() - 1);
+    assert_eq!(fake_blocks, pid_blocks);
+    assert_eq!(fake_resumed, pid_resumed);
+    assert_eq!(&fake_resumed[..], &fake_blocks[1 ..]);
+}
@@ -0,0 +1,55 @@
+use std::{
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use blockwheel_fs_ero::{
+    GenServer,
+    InfoError,
+    BlockingPid,
+};
+
+mod common;
+
+use common::{
+    Env,
+    ram_params,
+};
+
+const WHEEL_SIZE_BYTES: usize = 4 * 1024 * 1024;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn blocking_round_trip_from_plain_thread() {
+    let mut env = Env::new();
+    let pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+    let block_bytes = env.block(0, 4096);
+
+    let handle = std::thread::spawn(move || {
+        let mut blocking_pid = BlockingPid::new(pid.with_timeout(Duration::from_secs(10)));
+        let block_id = blocking_pid.write_block(block_bytes.clone()).unwrap();
+        assert_eq!(blocking_pid.read_block(block_id.clone()).unwrap().to_vec(), block_bytes.to_vec());
+        blocking_pid.flush().unwrap();
+
+        let mut blocks = blocking_pid.iter_blocks().unwrap();
+        assert_eq!(blocks.blocks_total_count, 1);
+        assert_eq!(blocks.next().unwrap().unwrap().0, block_id);
+        assert!(blocks.next().is_none());
+
+        blocking_pid.delete_block(block_id).unwrap();
+    });
+    tokio::task::spawn_blocking(move || handle.join().unwrap()).await.unwrap();
+}
+
+#[test]
+fn blocking_timeout_without_runtime() {
+    // the server is never run, so no reply ever comes
+    let gen_server = GenServer::new();
+    let mut blocking_pid = BlockingPid::new(gen_server.pid().with_timeout(Duration::from_millis(100)));
+
+    let started_at = Instant::now();
+    assert!(matches!(blocking_pid.info(), Err(InfoError::Timeout)));
+    assert!(started_at.elapsed() >= Duration::from_millis(100));
+    drop(gen_server);
+}
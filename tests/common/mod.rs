@@ -0,0 +1,135 @@
+#![allow(dead_code)]
+
+use std::{
+    path::{
+        PathBuf,
+    },
+    time::{
+        Duration,
+    },
+};
+
+use alloc_pool::{
+    bytes::{
+        Bytes,
+        BytesPool,
+    },
+};
+
+use ero::{
+    supervisor::{
+        SupervisorPid,
+        SupervisorGenServer,
+    },
+};
+
+use blockwheel_fs_ero::{
+    job,
+    Pid,
+    Health,
+    Params,
+    GenServer,
+    InterpreterParams,
+    RamInterpreterParams,
+    FixedFileInterpreterParams,
+};
+
+/// Everything a gen server needs to run: a thread pool, a supervisor and a
+/// blocks pool.
+pub struct Env {
+    pub thread_pool: edeltraud::Edeltraud<job::Job>,
+    pub supervisor_pid: SupervisorPid,
+    pub blocks_pool: BytesPool,
+}
+
+impl Env {
+    pub fn new() -> Env {
+        let thread_pool = edeltraud::Builder::new()
+            .worker_threads(4)
+            .build::<_, job::JobUnit<_>>()
+            .unwrap();
+        let supervisor_gen_server = SupervisorGenServer::new();
+        let supervisor_pid = supervisor_gen_server.pid();
+        tokio::spawn(supervisor_gen_server.run());
+        Env {
+            thread_pool,
+            supervisor_pid,
+            blocks_pool: BytesPool::new(),
+        }
+    }
+
+    pub fn start(&mut self, params: Params) -> Pid {
+        self.start_gen_server(GenServer::new(), params)
+    }
+
+    pub fn start_gen_server(&mut self, gen_server: GenServer, params: Params) -> Pid {
+        let pid = gen_server.pid();
+        let parent_supervisor = self.supervisor_pid.clone();
+        self.supervisor_pid.spawn_link_temporary(
+            gen_server.run(
+                parent_supervisor,
+                params,
+                self.blocks_pool.clone(),
+                self.thread_pool.handle(),
+            ),
+        );
+        pid
+    }
+
+    /// Block of `size` bytes derived from `seed`, so different blocks have
+    /// different contents.
+    pub fn block(&self, seed: u64, size: usize) -> Bytes {
+        let mut block_bytes = self.blocks_pool.lend();
+        let mut state = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        for _ in 0 .. size {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            block_bytes.push((state >> 56) as u8);
+        }
+        block_bytes.freeze()
+    }
+}
+
+pub fn ram_params(wheel_size_bytes: usize) -> Params {
+    Params {
+        interpreter: InterpreterParams::Ram(RamInterpreterParams {
+            init_wheel_size_bytes: wheel_size_bytes,
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+pub fn fixed_file_params(wheel_filename: PathBuf, wheel_size_bytes: usize) -> Params {
+    Params {
+        interpreter: InterpreterParams::FixedFile(FixedFileInterpreterParams {
+            wheel_filename,
+            init_wheel_size_bytes: wheel_size_bytes,
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Waits until the gen server watched by `health_rx` reports a health
+/// matching `pred`.
+pub async fn wait_health<P>(mut health_rx: tokio::sync::watch::Receiver<Health>, pred: P) -> Health
+where P: Fn(&Health) -> bool,
+{
+    tokio::time::timeout(Duration::from_secs(10), async move {
+        loop {
+            let health = health_rx.borrow_and_update().clone();
+            if pred(&health) {
+                return health;
+            }
+            health_rx.changed().await
+                .expect("gen server health sender is gone");
+        }
+    })
+    .await
+    .expect("gen server did not reach expected health in time")
+}
+
+/// Waits until the gen server watched by `health_rx` reports `Terminated`.
+pub async fn wait_terminated(health_rx: tokio::sync::watch::Receiver<Health>) {
+    wait_health(health_rx, |health| matches!(health, Health::Terminated)).await;
+}
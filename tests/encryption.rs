@@ -0,0 +1,126 @@
+use std::{
+    collections::{
+        HashMap,
+    },
+};
+
+use futures::{
+    StreamExt,
+};
+
+use blockwheel_fs_ero::{
+    block,
+    Pid,
+    GenServer,
+    EncryptionKeys,
+    ReadBlockError,
+    IterBlocksError,
+};
+
+mod common;
+
+use common::{
+    Env,
+    ram_params,
+    fixed_file_params,
+    wait_terminated,
+};
+
+const WHEEL_SIZE_BYTES: usize = 4 * 1024 * 1024;
+
+fn keys_v1() -> EncryptionKeys {
+    EncryptionKeys::new(1, [1; 32])
+}
+
+fn keys_v2() -> EncryptionKeys {
+    EncryptionKeys::new(2, [2; 32])
+}
+
+/// Drains the blocks stream, returning the blocks read and the ids of the
+/// blocks reported as corrupted.
+async fn collect_blocks(pid: &mut Pid) -> (HashMap<block::Id, Vec<u8>>, Vec<block::Id>) {
+    let mut blocks_stream = pid.iter_blocks().await.unwrap().into_stream();
+    let mut blocks = HashMap::new();
+    let mut corrupted_block_ids = Vec::new();
+    while let Some(item) = blocks_stream.next().await {
+        match item {
+            Ok((block_id, block_bytes)) =>
+                assert!(blocks.insert(block_id, block_bytes.to_vec()).is_none()),
+            Err(IterBlocksError::Corrupted { block_id, }) =>
+                corrupted_block_ids.push(block_id),
+            Err(error) =>
+                panic!("unexpected iteration error: {:?}", error),
+        }
+    }
+    (blocks, corrupted_block_ids)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn iter_blocks_decrypts_blocks() {
+    let mut env = Env::new();
+    let mut pid = env.start_gen_server(GenServer::new().with_encryption(keys_v1()), ram_params(WHEEL_SIZE_BYTES));
+
+    let mut expected = HashMap::new();
+    for seed in 0 .. 16 {
+        let block_bytes = env.block(seed, 1024);
+        let block_id = pid.write_block(block_bytes.clone()).await.unwrap();
+        expected.insert(block_id, block_bytes.to_vec());
+    }
+
+    let (blocks, corrupted_block_ids) = collect_blocks(&mut pid).await;
+    assert_eq!(blocks, expected);
+    assert!(corrupted_block_ids.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rotated_keys_keep_old_blocks_readable() {
+    let wheel_dir = tempfile::tempdir().unwrap();
+    let wheel_filename = wheel_dir.path().join("wheel");
+    let mut env = Env::new();
+
+    let mut expected = HashMap::new();
+    let old_block_id;
+    {
+        let mut pid = env.start_gen_server(
+            GenServer::new().with_encryption(keys_v1()),
+            fixed_file_params(wheel_filename.clone(), WHEEL_SIZE_BYTES),
+        );
+        let block_bytes = env.block(0, 1024);
+        old_block_id = pid.write_block(block_bytes.clone()).await.unwrap();
+        expected.insert(old_block_id.clone(), block_bytes.to_vec());
+        pid.flush().await.unwrap();
+        let health_rx = pid.health_watch();
+        pid.shutdown().await.unwrap();
+        wait_terminated(health_rx).await;
+    }
+
+    {
+        let mut pid = env.start_gen_server(
+            GenServer::new().with_encryption(keys_v2().with_decryption_key(1, [1; 32])),
+            fixed_file_params(wheel_filename.clone(), WHEEL_SIZE_BYTES),
+        );
+        let block_bytes = env.block(1, 1024);
+        let block_id = pid.write_block(block_bytes.clone()).await.unwrap();
+        expected.insert(block_id, block_bytes.to_vec());
+        assert_eq!(pid.read_block(old_block_id.clone()).await.unwrap().to_vec(), expected[&old_block_id]);
+        let (blocks, corrupted_block_ids) = collect_blocks(&mut pid).await;
+        assert_eq!(blocks, expected);
+        assert!(corrupted_block_ids.is_empty());
+        pid.flush().await.unwrap();
+        let health_rx = pid.health_watch();
+        pid.shutdown().await.unwrap();
+        wait_terminated(health_rx).await;
+    }
+
+    // once the retired key is dropped, blocks sealed with it are reported
+    // and the iteration goes on past them
+    let mut pid = env.start_gen_server(
+        GenServer::new().with_encryption(keys_v2()),
+        fixed_file_params(wheel_filename, WHEEL_SIZE_BYTES),
+    );
+    assert!(matches!(pid.read_block(old_block_id.clone()).await, Err(ReadBlockError::DecryptionFailed)));
+    let (blocks, corrupted_block_ids) = collect_blocks(&mut pid).await;
+    assert_eq!(corrupted_block_ids, vec![old_block_id.clone()]);
+    expected.remove(&old_block_id);
+    assert_eq!(blocks, expected);
+}
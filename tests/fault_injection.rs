@@ -0,0 +1,361 @@
+use std::{
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use futures::{
+    StreamExt,
+};
+
+use blockwheel_fs_ero::{
+    block,
+    Pid,
+    Fault,
+    Health,
+    Flushed,
+    GenServer,
+    WriteToken,
+    FaultInjector,
+    Backoff,
+    RestartPolicy,
+    GenServerError,
+    InfoError,
+    FlushError,
+    ReadBlockError,
+    WriteBlockError,
+    IterBlocksError,
+};
+
+mod common;
+
+use common::{
+    Env,
+    ram_params,
+    fixed_file_params,
+    wait_health,
+};
+
+const WHEEL_SIZE_BYTES: usize = 4 * 1024 * 1024;
+
+fn start(env: &mut Env, fault_injector: &FaultInjector, restart_policy: RestartPolicy) -> Pid {
+    env.start_gen_server(
+        GenServer::new()
+            .with_restart_policy(restart_policy)
+            .with_fault_injector(fault_injector.clone()),
+        ram_params(WHEEL_SIZE_BYTES),
+    )
+}
+
+/// Drains the stream, returning the number of blocks received and the error
+/// it ended with, if any.
+async fn drain_blocks(pid: &mut Pid) -> (usize, Option<IterBlocksError>) {
+    let mut blocks_stream = pid.iter_blocks().await.unwrap().into_stream();
+    let mut blocks_count = 0;
+    while let Some(item) = blocks_stream.next().await {
+        match item {
+            Ok(..) =>
+                blocks_count += 1,
+            Err(error) =>
+                return (blocks_count, Some(error)),
+        }
+    }
+    (blocks_count, None)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn canceled_replies_are_resubmitted() {
+    let mut env = Env::new();
+    let fault_injector = FaultInjector::new();
+    let mut pid = start(&mut env, &fault_injector, RestartPolicy::default());
+
+    let block_bytes = env.block(0, 1024);
+    fault_injector.inject(Fault::CancelReply, 3);
+    let block_id = pid.write_block_with_token(WriteToken(1), block_bytes.clone()).await.unwrap();
+    assert_eq!(fault_injector.pending(Fault::CancelReply), 0);
+
+    fault_injector.inject(Fault::CancelReply, 2);
+    assert_eq!(pid.read_block(block_id.clone()).await.unwrap().to_vec(), block_bytes.to_vec());
+    fault_injector.inject(Fault::CancelReply, 2);
+    assert!(matches!(pid.flush().await, Ok(Flushed)));
+    fault_injector.inject(Fault::CancelReply, 2);
+    pid.delete_block(block_id).await.unwrap();
+    assert_eq!(fault_injector.pending(Fault::CancelReply), 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn canceled_plain_write_has_unknown_outcome() {
+    let mut env = Env::new();
+    let fault_injector = FaultInjector::new();
+    let mut pid = start(&mut env, &fault_injector, RestartPolicy::default());
+
+    fault_injector.inject(Fault::CancelReply, 1);
+    assert!(matches!(pid.write_block(env.block(0, 1024)).await, Err(WriteBlockError::OutcomeUnknown)));
+    pid.write_block(env.block(1, 1024)).await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn disk_full_rejects_writes_only() {
+    let mut env = Env::new();
+    let fault_injector = FaultInjector::new();
+    let mut pid = start(&mut env, &fault_injector, RestartPolicy::default());
+
+    let block_id = pid.write_block(env.block(0, 1024)).await.unwrap();
+    fault_injector.inject(Fault::DiskFull, 2);
+
+    // reads do not use up the fault
+    pid.read_block(block_id).await.unwrap();
+    assert_eq!(fault_injector.pending(Fault::DiskFull), 2);
+
+    assert!(matches!(pid.write_block(env.block(1, 1024)).await, Err(WriteBlockError::NoSpaceLeft)));
+    assert!(matches!(
+        pid.write_block_with_token(WriteToken(1), env.block(2, 1024)).await,
+        Err(WriteBlockError::NoSpaceLeft),
+    ));
+    pid.write_block(env.block(3, 1024)).await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_request_crashes_server_without_restart() {
+    let mut env = Env::new();
+    let fault_injector = FaultInjector::new();
+    let mut pid = start(&mut env, &fault_injector, RestartPolicy::InstantCrash);
+
+    fault_injector.inject(Fault::FailRequest, 1);
+    assert!(matches!(pid.flush().await, Err(FlushError::GenServer(..))));
+    let health = wait_health(pid.health_watch(), |health| matches!(health, Health::Failed { .. })).await;
+    assert!(matches!(health, Health::Failed { reason, } if matches!(*reason, GenServerError::FaultInjectedOnRequest)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_request_is_resubmitted_after_restart() {
+    let wheel_dir = tempfile::tempdir().unwrap();
+    let mut env = Env::new();
+    let fault_injector = FaultInjector::new();
+    let mut pid = env.start_gen_server(
+        GenServer::new()
+            .with_restart_policy(RestartPolicy::InstantRestart { limit: None, })
+            .with_fault_injector(fault_injector.clone()),
+        fixed_file_params(wheel_dir.path().join("wheel"), WHEEL_SIZE_BYTES),
+    );
+
+    let block_bytes = env.block(0, 4096);
+    let block_id = pid.write_block(block_bytes.clone()).await.unwrap();
+    pid.flush().await.unwrap();
+
+    fault_injector.inject(Fault::FailRequest, 2);
+    assert_eq!(pid.read_block(block_id).await.unwrap().to_vec(), block_bytes.to_vec());
+    assert_eq!(fault_injector.pending(Fault::FailRequest), 0);
+    assert!(matches!(pid.health(), Health::Running));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn thread_pool_spawn_failure_on_request_crashes_server() {
+    let mut env = Env::new();
+    let fault_injector = FaultInjector::new();
+    let mut pid = start(&mut env, &fault_injector, RestartPolicy::InstantCrash);
+
+    fault_injector.inject(Fault::ThreadPoolSpawn, 1);
+    assert!(matches!(pid.read_block(block::Id::init()).await, Err(ReadBlockError::GenServer(..))));
+    let health = wait_health(pid.health_watch(), |health| matches!(health, Health::Failed { .. })).await;
+    assert!(matches!(health, Health::Failed { reason, } if matches!(*reason, GenServerError::FaultInjectedOnThreadPoolSpawn)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn lost_echo_crashes_server_without_restart() {
+    let mut env = Env::new();
+    let fault_injector = FaultInjector::new();
+    let mut pid = start(&mut env, &fault_injector, RestartPolicy::InstantCrash);
+
+    fault_injector.inject(Fault::LoseEcho, 1);
+    assert!(matches!(pid.info().await, Err(InfoError::GenServer(..))));
+    let health = wait_health(pid.health_watch(), |health| matches!(health, Health::Failed { .. })).await;
+    assert!(matches!(health, Health::Failed { reason, } if matches!(*reason, GenServerError::FaultInjectedOnEcho)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn lost_echo_is_resubmitted_after_restart() {
+    let mut env = Env::new();
+    let fault_injector = FaultInjector::new();
+    let mut pid = start(&mut env, &fault_injector, RestartPolicy::InstantRestart { limit: None, });
+
+    let block_id = pid.write_block(env.block(0, 1024)).await.unwrap();
+    fault_injector.inject(Fault::LoseEcho, 2);
+
+    // reads and iterators do not use up the fault
+    pid.read_block(block_id).await.unwrap();
+    assert!(drain_blocks(&mut pid).await.1.is_none());
+    assert_eq!(fault_injector.pending(Fault::LoseEcho), 2);
+
+    pid.info().await.unwrap();
+    assert!(matches!(pid.flush().await, Ok(Flushed)));
+    assert_eq!(fault_injector.pending(Fault::LoseEcho), 0);
+    assert!(matches!(pid.health(), Health::Running));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn iterator_faults_interrupt_only_the_stream() {
+    let mut env = Env::new();
+    let fault_injector = FaultInjector::new();
+    let mut pid = start(&mut env, &fault_injector, RestartPolicy::InstantCrash);
+
+    const BLOCKS_COUNT: usize = 16;
+    for seed in 0 .. BLOCKS_COUNT {
+        pid.write_block(env.block(seed as u64, 1024)).await.unwrap();
+    }
+
+    for fault in [Fault::ThreadPoolSpawn, Fault::CancelReply] {
+        let mut blocks_stream = pid.iter_blocks().await.unwrap().into_stream();
+        // the iterator is at most a couple of items ahead of the stream
+        assert!(blocks_stream.next().await.unwrap().is_ok());
+        fault_injector.inject(fault, 1);
+        let mut blocks_count = 1;
+        let error = loop {
+            match blocks_stream.next().await {
+                Some(Ok(..)) =>
+                    blocks_count += 1,
+                Some(Err(error)) =>
+                    break error,
+                None =>
+                    panic!("stream ended without an error with {:?} injected", fault),
+            }
+        };
+        assert!(matches!(error, IterBlocksError::Interrupted));
+        assert!(blocks_count < BLOCKS_COUNT);
+        assert_eq!(fault_injector.pending(fault), 0);
+
+        // the server itself keeps running
+        assert!(matches!(pid.health(), Health::Running));
+        assert!(matches!(drain_blocks(&mut pid).await, (BLOCKS_COUNT, None)));
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn corrupted_block_read_is_reported() {
+    let mut env = Env::new();
+    let fault_injector = FaultInjector::new();
+    let mut pid = start(&mut env, &fault_injector, RestartPolicy::InstantCrash);
+
+    let block_bytes = env.block(0, 1024);
+    let block_id = pid.write_block(block_bytes.clone()).await.unwrap();
+    fault_injector.inject(Fault::CorruptBlock, 1);
+    assert!(matches!(pid.read_block(block_id.clone()).await, Err(ReadBlockError::Corrupted)));
+    assert_eq!(pid.read_block(block_id).await.unwrap().to_vec(), block_bytes.to_vec());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn corruption_goes_unnoticed_without_checksums() {
+    let mut env = Env::new();
+    let fault_injector = FaultInjector::new();
+    let mut pid = env.start_gen_server(
+        GenServer::new()
+            .without_checksums()
+            .with_fault_injector(fault_injector.clone()),
+        ram_params(WHEEL_SIZE_BYTES),
+    );
+
+    let block_bytes = env.block(0, 1024);
+    let block_id = pid.write_block(block_bytes.clone()).await.unwrap();
+    fault_injector.inject(Fault::CorruptBlock, 1);
+    assert_ne!(pid.read_block(block_id).await.unwrap().to_vec(), block_bytes.to_vec());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn iterator_reports_corrupted_block_and_goes_on() {
+    let mut env = Env::new();
+    let fault_injector = FaultInjector::new();
+    let mut pid = start(&mut env, &fault_injector, RestartPolicy::InstantCrash);
+
+    const BLOCKS_COUNT: usize = 16;
+    for seed in 0 .. BLOCKS_COUNT {
+        pid.write_block(env.block(seed as u64, 1024)).await.unwrap();
+    }
+
+    fault_injector.inject(Fault::CorruptBlock, 1);
+    let mut blocks_stream = pid.iter_blocks().await.unwrap().into_stream();
+    let mut blocks_count = 0;
+    let mut corrupted_block_ids = Vec::new();
+    while let Some(item) = blocks_stream.next().await {
+        match item {
+            Ok(..) =>
+                blocks_count += 1,
+            Err(IterBlocksError::Corrupted { block_id, }) =>
+                corrupted_block_ids.push(block_id),
+            Err(error) =>
+                panic!("unexpected iteration error: {:?}", error),
+        }
+    }
+    assert_eq!(blocks_count, BLOCKS_COUNT - 1);
+    assert_eq!(corrupted_block_ids.len(), 1);
+
+    // the block itself is intact on the wheel
+    pid.read_block(corrupted_block_ids.pop().unwrap()).await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scrub_reports_corrupted_block() {
+    let mut env = Env::new();
+    let fault_injector = FaultInjector::new();
+    let mut pid = start(&mut env, &fault_injector, RestartPolicy::InstantCrash);
+
+    const BLOCKS_COUNT: usize = 16;
+    for seed in 0 .. BLOCKS_COUNT {
+        pid.write_block(env.block(seed as u64, 1024)).await.unwrap();
+    }
+
+    fault_injector.inject(Fault::CorruptBlock, 1);
+    let scrub_report = pid.scrub().await.unwrap();
+    assert_eq!(fault_injector.pending(Fault::CorruptBlock), 0);
+    assert_eq!(scrub_report.blocks_checked, BLOCKS_COUNT);
+    assert_eq!(scrub_report.blocks_without_checksum, 0);
+    assert_eq!(scrub_report.corrupted_block_ids.len(), 1);
+
+    // the next pass finds the wheel intact
+    let scrub_report = pid.scrub().await.unwrap();
+    assert_eq!(scrub_report.blocks_checked, BLOCKS_COUNT);
+    assert!(scrub_report.corrupted_block_ids.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn delayed_restart_reports_restarting_and_keeps_blocks() {
+    const INITIAL_DELAY: Duration = Duration::from_millis(300);
+
+    let wheel_dir = tempfile::tempdir().unwrap();
+    let mut env = Env::new();
+    let fault_injector = FaultInjector::new();
+    let mut pid = env.start_gen_server(
+        GenServer::new()
+            .with_restart_policy(RestartPolicy::DelayedRestart {
+                backoff: Backoff {
+                    initial_delay: INITIAL_DELAY,
+                    max_delay: Duration::from_secs(2),
+                    multiplier: 2,
+                },
+                limit: None,
+            })
+            .with_fault_injector(fault_injector.clone()),
+        fixed_file_params(wheel_dir.path().join("wheel"), WHEEL_SIZE_BYTES),
+    );
+
+    let block_bytes = env.block(0, 1024);
+    let block_id = pid.write_block(block_bytes.clone()).await.unwrap();
+    assert!(matches!(pid.flush().await, Ok(Flushed)));
+    wait_health(pid.health_watch(), |health| matches!(health, Health::Running)).await;
+
+    let health_rx = pid.health_watch();
+    fault_injector.inject(Fault::FailRequest, 1);
+    let started_at = Instant::now();
+    let mut info_pid = pid.clone();
+    // the failed request is resubmitted and served once the wheel is reopened
+    let (info_result, health) = futures::join!(
+        info_pid.info(),
+        wait_health(health_rx, |health| matches!(health, Health::Restarting { .. })),
+    );
+    info_result.unwrap();
+    assert!(started_at.elapsed() >= INITIAL_DELAY);
+    assert!(matches!(health, Health::Restarting { reason, } if matches!(*reason, GenServerError::FaultInjectedOnRequest)));
+
+    wait_health(pid.health_watch(), |health| matches!(health, Health::Running)).await;
+    assert_eq!(pid.read_block(block_id).await.unwrap().to_vec(), block_bytes.to_vec());
+}
@@ -0,0 +1,594 @@
+use std::{
+    time::{
+        Duration,
+    },
+    collections::{
+        HashMap,
+    },
+};
+
+use futures::{
+    StreamExt,
+};
+
+use blockwheel_fs_ero::{
+    block,
+    Pid,
+    Health,
+    Flushed,
+    Deleted,
+    GenServer,
+    Terminated,
+    WriteToken,
+    IterBlocksItem,
+    IterBlockIdsItem,
+    InfoError,
+    ReadBlockError,
+    WriteBlockError,
+    IterBlocksError,
+    DeleteBlockError,
+};
+
+mod common;
+
+use common::{
+    Env,
+    ram_params,
+    fixed_file_params,
+    wait_terminated,
+};
+
+const WHEEL_SIZE_BYTES: usize = 4 * 1024 * 1024;
+
+async fn collect_blocks(pid: &mut Pid) -> HashMap<block::Id, Vec<u8>> {
+    let iter_blocks = pid.iter_blocks().await.unwrap();
+    let blocks_total_count = iter_blocks.blocks_total_count;
+    let mut blocks_stream = iter_blocks.into_stream();
+    let mut blocks = HashMap::new();
+    while let Some(item) = blocks_stream.next().await {
+        let (block_id, block_bytes) = item.unwrap();
+        assert!(blocks.insert(block_id, block_bytes.to_vec()).is_none());
+    }
+    assert_eq!(blocks.len(), blocks_total_count);
+    blocks
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn write_read_delete_round_trip() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    let block_bytes = env.block(0, 4096);
+    let block_id = pid.write_block(block_bytes.clone()).await.unwrap();
+    assert_eq!(pid.read_block(block_id.clone()).await.unwrap().to_vec(), block_bytes.to_vec());
+
+    assert!(matches!(pid.delete_block(block_id.clone()).await, Ok(Deleted)));
+    assert!(matches!(pid.read_block(block_id).await, Err(ReadBlockError::NotFound)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn many_blocks_round_trip() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    let mut written = Vec::new();
+    for seed in 0 .. 64 {
+        let block_bytes = env.block(seed, 512 + seed as usize * 17);
+        let block_id = pid.write_block(block_bytes.clone()).await.unwrap();
+        written.push((block_id, block_bytes));
+    }
+    for (block_id, block_bytes) in written {
+        assert_eq!(pid.read_block(block_id).await.unwrap().to_vec(), block_bytes.to_vec());
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn not_found() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    let missing_block_id = block::Id::init();
+    assert!(matches!(pid.read_block(missing_block_id.clone()).await, Err(ReadBlockError::NotFound)));
+    assert!(matches!(pid.delete_block(missing_block_id).await, Err(DeleteBlockError::NotFound)));
+
+    let block_id = pid.write_block(env.block(0, 128)).await.unwrap();
+    assert!(matches!(pid.delete_block(block_id.clone()).await, Ok(Deleted)));
+    assert!(matches!(pid.delete_block(block_id).await, Err(DeleteBlockError::NotFound)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn no_space_left_on_small_wheel() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(64 * 1024));
+
+    let mut written = Vec::new();
+    let no_space_left = loop {
+        match pid.write_block(env.block(written.len() as u64, 4096)).await {
+            Ok(block_id) =>
+                written.push(block_id),
+            Err(error) =>
+                break error,
+        }
+        assert!(written.len() < 64, "small wheel accepted too many blocks");
+    };
+    assert!(matches!(no_space_left, WriteBlockError::NoSpaceLeft));
+    assert!(!written.is_empty());
+
+    // freeing a block makes room again
+    let block_id = written.pop().unwrap();
+    assert!(matches!(pid.delete_block(block_id).await, Ok(Deleted)));
+    pid.write_block(env.block(1000, 4096)).await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn iter_blocks_yields_every_block() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    let mut expected = HashMap::new();
+    for seed in 0 .. 32 {
+        let block_bytes = env.block(seed, 1024);
+        let block_id = pid.write_block(block_bytes.clone()).await.unwrap();
+        expected.insert(block_id, block_bytes.to_vec());
+    }
+    let deleted_block_id = expected.keys().next().unwrap().clone();
+    pid.delete_block(deleted_block_id.clone()).await.unwrap();
+    expected.remove(&deleted_block_id);
+
+    assert_eq!(collect_blocks(&mut pid).await, expected);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn iter_blocks_on_empty_wheel() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    assert!(collect_blocks(&mut pid).await.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn concurrent_iterators() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    let mut expected = HashMap::new();
+    for seed in 0 .. 32 {
+        let block_bytes = env.block(seed, 2048);
+        let block_id = pid.write_block(block_bytes.clone()).await.unwrap();
+        expected.insert(block_id, block_bytes.to_vec());
+    }
+
+    let iterators: Vec<_> = (0 .. 4)
+        .map(|_| {
+            let mut pid = pid.clone();
+            tokio::spawn(async move { collect_blocks(&mut pid).await })
+        })
+        .collect();
+    for iterator in iterators {
+        assert_eq!(iterator.await.unwrap(), expected);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn iter_blocks_resumes_from_cursor() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    let mut expected = HashMap::new();
+    for seed in 0 .. 16 {
+        let block_bytes = env.block(seed, 1024);
+        let block_id = pid.write_block(block_bytes.clone()).await.unwrap();
+        expected.insert(block_id, block_bytes.to_vec());
+    }
+
+    // take a few blocks and drop the iterator in the middle
+    let mut blocks = HashMap::new();
+    let mut iter_blocks = pid.iter_blocks().await.unwrap();
+    let mut cursor = None;
+    for _ in 0 .. 5 {
+        match iter_blocks.blocks_rx.next().await.unwrap() {
+            IterBlocksItem::Block { block_id, block_bytes, cursor: block_cursor, } => {
+                assert!(blocks.insert(block_id, block_bytes.to_vec()).is_none());
+                cursor = Some(block_cursor);
+            },
+            IterBlocksItem::Corrupted { block_id, .. } =>
+                panic!("block {:?} is corrupted", block_id),
+            IterBlocksItem::NoMoreBlocks =>
+                panic!("iteration ended too early"),
+        }
+    }
+    drop(iter_blocks);
+
+    let iter_blocks = pid.iter_blocks_from(cursor.unwrap()).await.unwrap();
+    assert_eq!(iter_blocks.blocks_total_count, expected.len());
+    let mut blocks_stream = iter_blocks.into_stream();
+    let mut resumed_count = 0;
+    while let Some(item) = blocks_stream.next().await {
+        let (block_id, block_bytes) = item.unwrap();
+        assert!(blocks.insert(block_id, block_bytes.to_vec()).is_none(), "block yielded twice");
+        resumed_count += 1;
+    }
+    assert_eq!(resumed_count, expected.len() - 5);
+    assert_eq!(blocks, expected);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn flush() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    assert!(matches!(pid.flush().await, Ok(Flushed)));
+    let block_id = pid.write_block(env.block(0, 1024)).await.unwrap();
+    assert!(matches!(pid.flush().await, Ok(Flushed)));
+    assert_eq!(pid.read_block(block_id).await.unwrap().to_vec(), env.block(0, 1024).to_vec());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn terminates_when_all_pids_are_dropped() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    pid.write_block(env.block(0, 1024)).await.unwrap();
+    let other_pid = pid.clone();
+    let health_rx = pid.health_watch();
+    assert!(!matches!(pid.health(), Health::Terminated));
+
+    drop(pid);
+    drop(other_pid);
+    wait_terminated(health_rx).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn requests_fail_after_shutdown() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    let mut other_pid = pid.clone();
+    pid.shutdown().await.unwrap();
+    wait_terminated(other_pid.health_watch()).await;
+    assert!(matches!(other_pid.write_block(env.block(0, 128)).await, Err(WriteBlockError::GenServer(..))));
+    assert!(matches!(other_pid.read_block(block::Id::init()).await, Err(ReadBlockError::GenServer(..))));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shutdown_closes_unread_iterator() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    for seed in 0 .. 16 {
+        pid.write_block(env.block(seed, 1024)).await.unwrap();
+    }
+    // the stream is held but never read until the shutdown completes
+    let mut blocks_stream = pid.iter_blocks().await.unwrap().into_stream();
+    let mut shutdown_pid = pid.with_timeout(Duration::from_millis(200));
+    let shutdown_result = tokio::time::timeout(Duration::from_secs(10), shutdown_pid.shutdown()).await
+        .expect("shutdown is blocked by an unread iterator");
+    assert!(matches!(shutdown_result, Ok(Terminated)));
+
+    let mut blocks_count = 0;
+    let error = loop {
+        match blocks_stream.next().await {
+            Some(Ok(..)) =>
+                blocks_count += 1,
+            Some(Err(error)) =>
+                break error,
+            None =>
+                panic!("closed iterator stream ended cleanly"),
+        }
+    };
+    assert!(matches!(error, IterBlocksError::Interrupted));
+    assert!(blocks_count < 16);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shutdown_lets_running_iterator_finish() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    let mut expected = HashMap::new();
+    for seed in 0 .. 16 {
+        let block_bytes = env.block(seed, 1024);
+        let block_id = pid.write_block(block_bytes.clone()).await.unwrap();
+        expected.insert(block_id, block_bytes.to_vec());
+    }
+    let mut blocks_stream = pid.iter_blocks().await.unwrap().into_stream();
+    let mut shutdown_pid = pid.clone();
+    let shutdown = tokio::spawn(async move { shutdown_pid.shutdown().await });
+
+    let mut blocks = HashMap::new();
+    while let Some(item) = blocks_stream.next().await {
+        let (block_id, block_bytes) = item.unwrap();
+        assert!(blocks.insert(block_id, block_bytes.to_vec()).is_none());
+    }
+    assert_eq!(blocks, expected);
+    assert!(matches!(shutdown.await.unwrap(), Ok(Terminated)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fixed_file_reopen_keeps_blocks() {
+    let wheel_dir = tempfile::tempdir().unwrap();
+    let wheel_filename = wheel_dir.path().join("wheel");
+    let mut env = Env::new();
+
+    let mut expected = HashMap::new();
+    {
+        let mut pid = env.start(fixed_file_params(wheel_filename.clone(), WHEEL_SIZE_BYTES));
+        for seed in 0 .. 16 {
+            let block_bytes = env.block(seed, 4096);
+            let block_id = pid.write_block(block_bytes.clone()).await.unwrap();
+            expected.insert(block_id, block_bytes.to_vec());
+        }
+        let deleted_block_id = expected.keys().next().unwrap().clone();
+        pid.delete_block(deleted_block_id.clone()).await.unwrap();
+        expected.remove(&deleted_block_id);
+        pid.flush().await.unwrap();
+        let health_rx = pid.health_watch();
+        pid.shutdown().await.unwrap();
+        wait_terminated(health_rx).await;
+    }
+
+    let mut pid = env.start(fixed_file_params(wheel_filename, WHEEL_SIZE_BYTES));
+    for (block_id, block_bytes) in &expected {
+        assert_eq!(&pid.read_block(block_id.clone()).await.unwrap().to_vec(), block_bytes);
+    }
+    assert_eq!(collect_blocks(&mut pid).await, expected);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shutdown_serves_accepted_requests() {
+    let wheel_dir = tempfile::tempdir().unwrap();
+    let wheel_filename = wheel_dir.path().join("wheel");
+    let mut env = Env::new();
+
+    let mut expected = HashMap::new();
+    {
+        let mut pid = env.start(fixed_file_params(wheel_filename.clone(), WHEEL_SIZE_BYTES));
+        let mut replies = Vec::new();
+        for seed in 0 .. 16 {
+            let block_bytes = env.block(seed, 4096);
+            replies.push((pid.submit_write_block(block_bytes.clone()).await.unwrap(), block_bytes));
+        }
+        let health_rx = pid.health_watch();
+        assert!(matches!(pid.shutdown().await, Ok(Terminated)));
+        for (reply, block_bytes) in replies {
+            expected.insert(reply.await.unwrap(), block_bytes.to_vec());
+        }
+        wait_terminated(health_rx).await;
+    }
+
+    // the final flush has made every accepted write durable
+    let mut pid = env.start(fixed_file_params(wheel_filename, WHEEL_SIZE_BYTES));
+    assert_eq!(collect_blocks(&mut pid).await, expected);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn requests_time_out_when_server_does_not_reply() {
+    // the server is never run, so requests are accepted but never served
+    let gen_server = GenServer::new();
+    let mut pid = gen_server.pid().with_timeout(Duration::from_millis(100));
+
+    assert!(matches!(pid.info().await, Err(InfoError::Timeout)));
+    assert!(matches!(pid.read_block(block::Id::init()).await, Err(ReadBlockError::Timeout)));
+    assert!(matches!(pid.read_blocks(vec![block::Id::init()]).await, Err(ReadBlockError::Timeout)));
+    drop(gen_server);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn batch_round_trip() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    let blocks_bytes: Vec<_> = (0 .. 8)
+        .map(|seed| env.block(seed, 1024))
+        .collect();
+    let block_ids: Vec<_> = pid.write_blocks(blocks_bytes.clone()).await.unwrap()
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(block_ids.len(), blocks_bytes.len());
+    let deleted_block_id = pid.write_block(env.block(100, 1024)).await.unwrap();
+    pid.delete_block(deleted_block_id.clone()).await.unwrap();
+
+    let mut read_block_ids = block_ids.clone();
+    read_block_ids.push(deleted_block_id);
+    let read_results = pid.read_blocks(read_block_ids).await.unwrap();
+    for (read_result, block_bytes) in read_results.iter().zip(&blocks_bytes) {
+        assert_eq!(read_result.as_ref().unwrap().to_vec(), block_bytes.to_vec());
+    }
+    assert!(matches!(read_results.last(), Some(Err(ReadBlockError::NotFound))));
+
+    let delete_results = pid.delete_blocks(block_ids.clone()).await.unwrap();
+    assert!(delete_results.iter().all(|delete_result| matches!(delete_result, Ok(Deleted))));
+    let read_results = pid.read_blocks(block_ids).await.unwrap();
+    assert!(read_results.iter().all(|read_result| matches!(read_result, Err(ReadBlockError::NotFound))));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn write_token_is_written_once() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    let block_bytes = env.block(0, 1024);
+    let mut other_pid = pid.clone();
+    let (block_id, concurrent_block_id) = futures::join!(
+        pid.write_block_with_token(WriteToken(7), block_bytes.clone()),
+        other_pid.write_block_with_token(WriteToken(7), block_bytes.clone()),
+    );
+    let block_id = block_id.unwrap();
+    assert_eq!(concurrent_block_id.unwrap(), block_id);
+    assert_eq!(pid.write_block_with_token(WriteToken(7), block_bytes.clone()).await.unwrap(), block_id);
+
+    let other_block_id = pid.write_block_with_token(WriteToken(8), block_bytes).await.unwrap();
+    assert_ne!(other_block_id, block_id);
+    assert_eq!(collect_blocks(&mut pid).await.len(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stats_count_operations() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    let block_id = pid.write_block(env.block(0, 1000)).await.unwrap();
+    pid.write_block(env.block(1, 3000)).await.unwrap();
+    let deleted_block_id = pid.write_block(env.block(2, 500)).await.unwrap();
+    pid.delete_block(deleted_block_id.clone()).await.unwrap();
+    pid.read_block(block_id.clone()).await.unwrap();
+    assert!(matches!(pid.read_block(deleted_block_id).await, Err(ReadBlockError::NotFound)));
+    pid.delete_block(block_id).await.unwrap();
+
+    let stats = pid.stats().await.unwrap();
+    assert_eq!(stats.write_block.requests, 3);
+    assert_eq!(stats.write_block.successes, 3);
+    assert_eq!(stats.write_block.latency.count(), 3);
+    assert_eq!(stats.read_block.requests, 2);
+    assert_eq!(stats.read_block.successes, 1);
+    assert_eq!(stats.read_block.not_found, 1);
+    assert_eq!(stats.delete_block.successes, 2);
+    assert_eq!(stats.bytes_written, 4500);
+    assert_eq!(stats.bytes_read, 1000);
+    assert_eq!(stats.active_iterators, 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pipelined_read_and_delete() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    let mut written = Vec::new();
+    for seed in 0 .. 8 {
+        let block_bytes = env.block(seed, 1024);
+        let block_id = pid.write_block(block_bytes.clone()).await.unwrap();
+        written.push((block_id, block_bytes));
+    }
+    let deleted_block_id = pid.write_block(env.block(100, 1024)).await.unwrap();
+    pid.delete_block(deleted_block_id.clone()).await.unwrap();
+
+    // all requests are in flight before any reply is awaited
+    let mut read_replies = Vec::new();
+    for (block_id, _) in &written {
+        read_replies.push(pid.submit_read_block(block_id.clone()).await.unwrap());
+    }
+    let missing_read_reply = pid.submit_read_block(deleted_block_id.clone()).await.unwrap();
+    for (read_reply, (_, block_bytes)) in read_replies.into_iter().zip(&written) {
+        assert_eq!(read_reply.await.unwrap().to_vec(), block_bytes.to_vec());
+    }
+    assert!(matches!(missing_read_reply.await, Err(ReadBlockError::NotFound)));
+
+    let mut delete_replies = Vec::new();
+    for (block_id, _) in &written {
+        delete_replies.push(pid.submit_delete_block(block_id.clone()).await.unwrap());
+    }
+    let missing_delete_reply = pid.submit_delete_block(deleted_block_id).await.unwrap();
+    for delete_reply in delete_replies {
+        assert!(matches!(delete_reply.await, Ok(Deleted)));
+    }
+    assert!(matches!(missing_delete_reply.await, Err(DeleteBlockError::NotFound)));
+    assert!(collect_blocks(&mut pid).await.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn iter_block_ids_matches_iter_blocks_without_payload() {
+    let mut env = Env::new();
+    // without checksums the block size on the wheel is the size written
+    let mut pid = env.start_gen_server(GenServer::new().without_checksums(), ram_params(WHEEL_SIZE_BYTES));
+
+    let mut expected_sizes = HashMap::new();
+    for seed in 0 .. 16 {
+        let block_bytes = env.block(seed, 512 + seed as usize * 64);
+        let block_id = pid.write_block(block_bytes.clone()).await.unwrap();
+        expected_sizes.insert(block_id, block_bytes.len());
+    }
+
+    let iter_block_ids = pid.iter_block_ids().await.unwrap();
+    assert_eq!(iter_block_ids.blocks_total_count, expected_sizes.len());
+    let mut block_ids_rx = iter_block_ids.block_ids_rx;
+    let mut block_sizes = HashMap::new();
+    loop {
+        match block_ids_rx.next().await.unwrap() {
+            IterBlockIdsItem::BlockId { block_id, block_size, } =>
+                assert!(block_sizes.insert(block_id, block_size).is_none()),
+            IterBlockIdsItem::NoMoreBlocks =>
+                break,
+        }
+    }
+    assert_eq!(block_sizes, expected_sizes);
+    assert_eq!(pid.stats().await.unwrap().bytes_read, 0);
+
+    let blocks = collect_blocks(&mut pid).await;
+    let mut block_ids: Vec<_> = blocks.keys().cloned().collect();
+    let mut expected_block_ids: Vec<_> = block_sizes.keys().cloned().collect();
+    block_ids.sort();
+    expected_block_ids.sort();
+    assert_eq!(block_ids, expected_block_ids);
+    let total_size: usize = block_sizes.values().sum();
+    assert_eq!(pid.stats().await.unwrap().bytes_read, total_size as u64);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scrub_counts_blocks_without_checksum() {
+    const BLOCKS_COUNT: usize = 8;
+    let mut env = Env::new();
+
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+    for seed in 0 .. BLOCKS_COUNT {
+        pid.write_block(env.block(seed as u64, 1024)).await.unwrap();
+    }
+    let scrub_report = pid.scrub().await.unwrap();
+    assert_eq!(scrub_report.blocks_checked, BLOCKS_COUNT);
+    assert_eq!(scrub_report.blocks_without_checksum, 0);
+    assert!(scrub_report.corrupted_block_ids.is_empty());
+
+    let mut pid = env.start_gen_server(GenServer::new().without_checksums(), ram_params(WHEEL_SIZE_BYTES));
+    for seed in 0 .. BLOCKS_COUNT {
+        pid.write_block(env.block(seed as u64, 1024)).await.unwrap();
+    }
+    let scrub_report = pid.scrub().await.unwrap();
+    assert_eq!(scrub_report.blocks_checked, BLOCKS_COUNT);
+    assert_eq!(scrub_report.blocks_without_checksum, BLOCKS_COUNT);
+    assert!(scrub_report.corrupted_block_ids.is_empty());
+}
+
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+#[tokio::test(flavor = "multi_thread")]
+async fn compressed_blocks_round_trip() {
+    use blockwheel_fs_ero::Compression;
+
+    let compressions = [
+        #[cfg(feature = "zstd")]
+        Compression::Zstd { level: 3, },
+        #[cfg(feature = "lz4")]
+        Compression::Lz4,
+    ];
+    for compression in compressions {
+        let mut env = Env::new();
+        let mut pid = env.start_gen_server(GenServer::new().with_compression(compression), ram_params(WHEEL_SIZE_BYTES));
+
+        let mut expected = HashMap::new();
+        for seed in 0 .. 4 {
+            let mut block_bytes = env.blocks_pool.lend();
+            for byte in env.block(seed, 64).iter().cycle().take(16 * 1024) {
+                block_bytes.push(*byte);
+            }
+            let block_bytes = block_bytes.freeze();
+            let block_id = pid.write_block(block_bytes.clone()).await.unwrap();
+            assert_eq!(pid.read_block(block_id.clone()).await.unwrap().to_vec(), block_bytes.to_vec());
+            expected.insert(block_id, block_bytes.to_vec());
+        }
+        assert_eq!(collect_blocks(&mut pid).await, expected, "{:?}", compression);
+
+        // blocks take less room on the wheel than they were written with
+        let mut block_ids_rx = pid.iter_block_ids().await.unwrap().block_ids_rx;
+        let mut blocks_count = 0;
+        loop {
+            match block_ids_rx.next().await.unwrap() {
+                IterBlockIdsItem::BlockId { block_id, block_size, } => {
+                    assert!(block_size < expected[&block_id].len(), "{:?}", compression);
+                    blocks_count += 1;
+                },
+                IterBlockIdsItem::NoMoreBlocks =>
+                    break,
+            }
+        }
+        assert_eq!(blocks_count, expected.len());
+    }
+}
@@ -0,0 +1,106 @@
+use alloc_pool::{
+    bytes::{
+        Bytes,
+    },
+};
+
+use blockwheel_fs_ero::{
+    KvPid,
+    ReadBlockError,
+    DeleteBlockError,
+};
+
+mod common;
+
+use common::{
+    Env,
+    ram_params,
+};
+
+const WHEEL_SIZE_BYTES: usize = 4 * 1024 * 1024;
+
+/// Record as laid out by `KvPid`, see the header description in `kv.rs`.
+fn record(env: &Env, seq: u64, key: &[u8], value: &[u8]) -> Bytes {
+    let mut block_bytes = env.blocks_pool.lend();
+    block_bytes.extend_from_slice(b"bwkv");
+    block_bytes.push(1);
+    block_bytes.extend_from_slice(&seq.to_le_bytes());
+    block_bytes.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    let checksum = crc32c::crc32c(&block_bytes);
+    let checksum = crc32c::crc32c_append(checksum, key);
+    let checksum = crc32c::crc32c_append(checksum, value);
+    block_bytes.extend_from_slice(&checksum.to_le_bytes());
+    block_bytes.extend_from_slice(key);
+    block_bytes.extend_from_slice(value);
+    block_bytes.freeze()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn values_survive_reopen() {
+    let mut env = Env::new();
+    let pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+    let mut kv = KvPid::open(pid.clone(), env.blocks_pool.clone()).await.unwrap();
+
+    kv.put(b"a", env.block(0, 1024)).await.unwrap();
+    kv.put(b"b", env.block(1, 1024)).await.unwrap();
+    kv.put(b"a", env.block(2, 1024)).await.unwrap();
+    kv.remove(b"b").await.unwrap();
+
+    let mut kv = KvPid::open(pid, env.blocks_pool.clone()).await.unwrap();
+    assert_eq!(kv.keys(), vec![b"a".to_vec()]);
+    assert_eq!(kv.get(b"a").await.unwrap().to_vec(), env.block(2, 1024).to_vec());
+    assert!(matches!(kv.get(b"b").await, Err(ReadBlockError::NotFound)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn open_leaves_foreign_blocks_alone() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    // looks like a record header but is not one
+    let mut foreign_bytes = env.blocks_pool.lend();
+    foreign_bytes.extend_from_slice(b"bwkv");
+    foreign_bytes.extend_from_slice(&env.block(0, 1024));
+    let foreign_bytes = foreign_bytes.freeze();
+    let foreign_block_id = pid.write_block(foreign_bytes.clone()).await.unwrap();
+    let foreign_copy_block_id = pid.write_block(foreign_bytes.clone()).await.unwrap();
+
+    let kv = KvPid::open(pid.clone(), env.blocks_pool.clone()).await.unwrap();
+    assert!(kv.keys().is_empty());
+    assert_eq!(pid.read_block(foreign_block_id).await.unwrap().to_vec(), foreign_bytes.to_vec());
+    assert_eq!(pid.read_block(foreign_copy_block_id).await.unwrap().to_vec(), foreign_bytes.to_vec());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn open_keeps_record_with_highest_sequence_number() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    // the newest record is written to the wheel before the older one
+    let newest_block_id = pid.write_block(record(&env, 5, b"a", b"newest")).await.unwrap();
+    let oldest_block_id = pid.write_block(record(&env, 4, b"a", b"oldest")).await.unwrap();
+
+    let mut kv = KvPid::open(pid.clone(), env.blocks_pool.clone()).await.unwrap();
+    assert_eq!(kv.get(b"a").await.unwrap().to_vec(), b"newest".to_vec());
+    assert!(matches!(pid.read_block(oldest_block_id).await, Err(ReadBlockError::NotFound)));
+    pid.read_block(newest_block_id.clone()).await.unwrap();
+
+    // sequence numbers go on from the highest one found
+    kv.put(b"a", env.block(0, 1024)).await.unwrap();
+    assert!(matches!(pid.read_block(newest_block_id).await, Err(ReadBlockError::NotFound)));
+    let mut kv = KvPid::open(pid, env.blocks_pool.clone()).await.unwrap();
+    assert_eq!(kv.get(b"a").await.unwrap().to_vec(), env.block(0, 1024).to_vec());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_remove_keeps_key() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+    let mut kv = KvPid::open(pid.clone(), env.blocks_pool.clone()).await.unwrap();
+
+    kv.put(b"a", env.block(0, 1024)).await.unwrap();
+    pid.shutdown().await.unwrap();
+
+    assert!(matches!(kv.remove(b"a").await, Err(DeleteBlockError::GenServer(..))));
+    assert_eq!(kv.keys(), vec![b"a".to_vec()]);
+}
@@ -0,0 +1,103 @@
+use futures::{
+    StreamExt,
+};
+
+use blockwheel_fs_ero::{
+    Pid,
+    Deleted,
+    MirroredPid,
+    ResyncReport,
+    ReadBlockError,
+};
+
+mod common;
+
+use common::{
+    Env,
+    ram_params,
+};
+
+const WHEEL_SIZE_BYTES: usize = 4 * 1024 * 1024;
+
+async fn open(env: &mut Env) -> (MirroredPid, Pid, Pid) {
+    let primary = env.start(ram_params(WHEEL_SIZE_BYTES));
+    let secondary = env.start(ram_params(WHEEL_SIZE_BYTES));
+    let mirrored = MirroredPid::open(primary.clone(), secondary.clone(), env.blocks_pool.clone()).await
+        .unwrap();
+    (mirrored, primary, secondary)
+}
+
+async fn blocks_count(pid: &mut Pid) -> usize {
+    let mut blocks_stream = pid.iter_blocks().await.unwrap().into_stream();
+    let mut blocks_count = 0;
+    while let Some(item) = blocks_stream.next().await {
+        item.unwrap();
+        blocks_count += 1;
+    }
+    blocks_count
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn read_falls_back_to_secondary() {
+    let mut env = Env::new();
+    let (mut mirrored, mut primary, _secondary) = open(&mut env).await;
+
+    let block_bytes = env.block(0, 4096);
+    let block_id = mirrored.write_block(block_bytes.clone()).await.unwrap();
+    primary.delete_block(block_id.clone()).await.unwrap();
+
+    assert_eq!(mirrored.read_block(block_id).await.unwrap().to_vec(), block_bytes.to_vec());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn delete_after_reopen_is_not_resurrected() {
+    let mut env = Env::new();
+    let (mut mirrored, primary, mut secondary) = open(&mut env).await;
+
+    let mut block_ids = Vec::new();
+    for seed in 0 .. 4 {
+        block_ids.push(mirrored.write_block(env.block(seed, 1024)).await.unwrap());
+    }
+
+    // the mapping is rebuilt from the secondary wheel
+    let mut mirrored = MirroredPid::open(primary, secondary.clone(), env.blocks_pool.clone()).await
+        .unwrap();
+    let deleted_block_id = block_ids.pop().unwrap();
+    assert!(matches!(mirrored.delete_block(deleted_block_id.clone()).await, Ok(Deleted)));
+    assert_eq!(blocks_count(&mut secondary).await, 3);
+
+    let report = mirrored.resync().await.unwrap();
+    assert_eq!(report, ResyncReport { matched: 3, copied_to_secondary: 0, copied_to_primary: 0, });
+    assert!(matches!(mirrored.read_block(deleted_block_id).await, Err(ReadBlockError::NotFound)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn resync_copies_missing_blocks_both_ways() {
+    let mut env = Env::new();
+    let (mut mirrored, mut primary, mut secondary) = open(&mut env).await;
+
+    // lost by the primary
+    let lost_bytes = env.block(0, 2048);
+    let lost_block_id = mirrored.write_block(lost_bytes.clone()).await.unwrap();
+    primary.delete_block(lost_block_id).await.unwrap();
+    // never written to the secondary
+    let primary_only_bytes = env.block(1, 2048);
+    let primary_only_block_id = primary.write_block(primary_only_bytes.clone()).await.unwrap();
+    // not written through the mirror at all, so it must be left alone
+    secondary.write_block(env.block(2, 2048)).await.unwrap();
+
+    let report = mirrored.resync().await.unwrap();
+    assert_eq!(report, ResyncReport { matched: 0, copied_to_secondary: 1, copied_to_primary: 1, });
+    assert_eq!(blocks_count(&mut primary).await, 2);
+    assert_eq!(blocks_count(&mut secondary).await, 3);
+
+    let report = mirrored.resync().await.unwrap();
+    assert_eq!(report, ResyncReport { matched: 2, copied_to_secondary: 0, copied_to_primary: 0, });
+
+    // the primary only block is now readable from the secondary as well
+    primary.delete_block(primary_only_block_id.clone()).await.unwrap();
+    assert_eq!(
+        mirrored.read_block(primary_only_block_id).await.unwrap().to_vec(),
+        primary_only_bytes.to_vec(),
+    );
+}
@@ -0,0 +1,181 @@
+//! Runs random sequences of requests through a real gen server and a
+//! `HashMap` model of the wheel, comparing both after each step.
+
+use std::{
+    path::{
+        Path,
+    },
+    collections::{
+        HashMap,
+    },
+};
+
+use futures::{
+    StreamExt,
+};
+
+use proptest::{
+    prelude::*,
+};
+
+use blockwheel_fs_ero::{
+    block,
+    Pid,
+    Params,
+    Deleted,
+    ReadBlockError,
+    DeleteBlockError,
+};
+
+mod common;
+
+use common::{
+    Env,
+    ram_params,
+    fixed_file_params,
+    wait_terminated,
+};
+
+const WHEEL_SIZE_BYTES: usize = 8 * 1024 * 1024;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Write { seed: u64, size: usize, },
+    /// Reads one of the blocks written so far, possibly an already deleted one.
+    Read { index: usize, },
+    /// Deletes one of the blocks written so far, possibly an already deleted one.
+    Delete { index: usize, },
+    IterBlocks,
+    Flush,
+    /// Shuts the gen server down and reopens the same wheel, only generated
+    /// for file backed wheels.
+    Restart,
+}
+
+fn op_strategy(with_restarts: bool) -> BoxedStrategy<Op> {
+    let op = prop_oneof![
+        4 => (any::<u64>(), 1usize .. 16384).prop_map(|(seed, size)| Op::Write { seed, size, }),
+        3 => any::<usize>().prop_map(|index| Op::Read { index, }),
+        2 => any::<usize>().prop_map(|index| Op::Delete { index, }),
+        1 => Just(Op::IterBlocks),
+        1 => Just(Op::Flush),
+    ];
+    if with_restarts {
+        prop_oneof![10 => op, 1 => Just(Op::Restart)].boxed()
+    } else {
+        op.boxed()
+    }
+}
+
+#[derive(Default)]
+struct Model {
+    blocks: HashMap<block::Id, Vec<u8>>,
+    written_ids: Vec<block::Id>,
+}
+
+impl Model {
+    fn pick(&self, index: usize) -> Option<block::Id> {
+        if self.written_ids.is_empty() {
+            None
+        } else {
+            Some(self.written_ids[index % self.written_ids.len()].clone())
+        }
+    }
+}
+
+async fn check_iter_blocks(pid: &mut Pid, model: &Model) {
+    let iter_blocks = pid.iter_blocks().await.unwrap();
+    assert_eq!(iter_blocks.blocks_total_count, model.blocks.len());
+    let mut blocks_stream = iter_blocks.into_stream();
+    let mut blocks = HashMap::new();
+    while let Some(item) = blocks_stream.next().await {
+        let (block_id, block_bytes) = item.unwrap();
+        assert!(blocks.insert(block_id, block_bytes.to_vec()).is_none(), "block yielded twice");
+    }
+    assert_eq!(blocks, model.blocks);
+}
+
+async fn shutdown(mut pid: Pid) {
+    let health_rx = pid.health_watch();
+    pid.shutdown().await.unwrap();
+    wait_terminated(health_rx).await;
+}
+
+async fn run_ops<F>(ops: Vec<Op>, make_params: F) where F: Fn() -> Params {
+    let mut env = Env::new();
+    let mut pid = env.start(make_params());
+    let mut model = Model::default();
+
+    for op in ops {
+        match op {
+            Op::Write { seed, size, } => {
+                let block_bytes = env.block(seed, size);
+                let block_id = pid.write_block(block_bytes.clone()).await.unwrap();
+                let prev = model.blocks.insert(block_id.clone(), block_bytes.to_vec());
+                assert!(prev.is_none(), "block id {:?} handed out twice", block_id);
+                model.written_ids.push(block_id);
+            },
+            Op::Read { index, } => {
+                let Some(block_id) = model.pick(index) else { continue; };
+                match (pid.read_block(block_id.clone()).await, model.blocks.get(&block_id)) {
+                    (Ok(block_bytes), Some(expected)) =>
+                        assert_eq!(&block_bytes.to_vec(), expected),
+                    (Err(ReadBlockError::NotFound), None) =>
+                        (),
+                    (result, expected) =>
+                        panic!("read_block {:?} returned {:?}, model has {:?}", block_id, result.map(|b| b.len()), expected.map(Vec::len)),
+                }
+            },
+            Op::Delete { index, } => {
+                let Some(block_id) = model.pick(index) else { continue; };
+                match (pid.delete_block(block_id.clone()).await, model.blocks.remove(&block_id)) {
+                    (Ok(Deleted), Some(..)) | (Err(DeleteBlockError::NotFound), None) =>
+                        (),
+                    (result, expected) =>
+                        panic!("delete_block {:?} returned {:?}, model has {:?}", block_id, result, expected.map(|b| b.len())),
+                }
+            },
+            Op::IterBlocks =>
+                check_iter_blocks(&mut pid, &model).await,
+            Op::Flush => {
+                pid.flush().await.unwrap();
+            },
+            Op::Restart => {
+                pid.flush().await.unwrap();
+                shutdown(pid).await;
+                pid = env.start(make_params());
+            },
+        }
+    }
+
+    check_iter_blocks(&mut pid, &model).await;
+    shutdown(pid).await;
+}
+
+fn block_on<F>(future: F) where F: std::future::Future<Output = ()> {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+fn file_params(wheel_filename: &Path) -> Params {
+    fixed_file_params(wheel_filename.to_path_buf(), WHEEL_SIZE_BYTES)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn ram_wheel_matches_model(ops in prop::collection::vec(op_strategy(false), 1 .. 64)) {
+        block_on(run_ops(ops, || ram_params(WHEEL_SIZE_BYTES)));
+    }
+
+    #[test]
+    fn file_wheel_matches_model_across_restarts(ops in prop::collection::vec(op_strategy(true), 1 .. 64)) {
+        let wheel_dir = tempfile::tempdir().unwrap();
+        let wheel_filename = wheel_dir.path().join("wheel");
+        block_on(run_ops(ops, || file_params(&wheel_filename)));
+    }
+}
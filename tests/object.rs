@@ -0,0 +1,104 @@
+use futures::{
+    StreamExt,
+};
+
+use blockwheel_fs_ero::{
+    Deleted,
+    ReadBlockError,
+    ReadObjectError,
+    WriteObjectError,
+    DeleteObjectError,
+};
+
+mod common;
+
+use common::{
+    Env,
+    ram_params,
+};
+
+const WHEEL_SIZE_BYTES: usize = 4 * 1024 * 1024;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn object_round_trip() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    // the last chunk is shorter than the others
+    let object_bytes = env.block(0, 10 * 1024 + 100);
+    let manifest_block_id = pid.write_object(&env.blocks_pool, &object_bytes, 1024).await.unwrap();
+
+    let read_bytes = pid.read_object(&env.blocks_pool, manifest_block_id.clone()).await.unwrap();
+    assert_eq!(read_bytes.to_vec(), object_bytes.to_vec());
+
+    let mut chunks = pid.read_object_stream(manifest_block_id.clone()).await.unwrap();
+    let mut streamed_bytes = Vec::new();
+    let mut chunks_count = 0;
+    while let Some(chunk) = chunks.next().await {
+        streamed_bytes.extend_from_slice(&chunk.unwrap());
+        chunks_count += 1;
+    }
+    assert_eq!(chunks_count, 11);
+    assert_eq!(streamed_bytes, object_bytes.to_vec());
+
+    assert!(matches!(pid.delete_object(manifest_block_id.clone()).await, Ok(Deleted)));
+    assert_eq!(pid.iter_blocks().await.unwrap().blocks_total_count, 0);
+    assert!(matches!(
+        pid.read_object(&env.blocks_pool, manifest_block_id).await,
+        Err(ReadObjectError::ReadBlock(ReadBlockError::NotFound)),
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn plain_block_is_not_a_manifest() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    let block_id = pid.write_block(env.block(0, 1024)).await.unwrap();
+    assert!(matches!(
+        pid.read_object(&env.blocks_pool, block_id.clone()).await,
+        Err(ReadObjectError::InvalidManifest),
+    ));
+    assert!(matches!(pid.read_object_stream(block_id.clone()).await, Err(ReadObjectError::InvalidManifest)));
+    assert!(matches!(
+        pid.delete_object(block_id.clone()).await,
+        Err(DeleteObjectError::ReadManifest(ReadObjectError::InvalidManifest)),
+    ));
+    pid.read_block(block_id).await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn zero_chunk_size_is_rejected() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    let object_bytes = env.block(0, 1024);
+    assert!(matches!(
+        pid.write_object(&env.blocks_pool, &object_bytes, 0).await,
+        Err(WriteObjectError::ZeroChunkSize),
+    ));
+    assert_eq!(pid.iter_blocks().await.unwrap().blocks_total_count, 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn foreign_block_with_magic_is_not_a_manifest() {
+    let mut env = Env::new();
+    let mut pid = env.start(ram_params(WHEEL_SIZE_BYTES));
+
+    // looks like a manifest header but claims a huge object
+    let mut foreign_bytes = env.blocks_pool.lend();
+    foreign_bytes.extend_from_slice(b"bwob");
+    foreign_bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+    foreign_bytes.extend_from_slice(&env.block(0, 64));
+    let block_id = pid.write_block(foreign_bytes.freeze()).await.unwrap();
+
+    assert!(matches!(
+        pid.read_object(&env.blocks_pool, block_id.clone()).await,
+        Err(ReadObjectError::InvalidManifest),
+    ));
+    assert!(matches!(
+        pid.delete_object(block_id.clone()).await,
+        Err(DeleteObjectError::ReadManifest(ReadObjectError::InvalidManifest)),
+    ));
+    pid.read_block(block_id).await.unwrap();
+}
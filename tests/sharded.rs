@@ -0,0 +1,88 @@
+use std::{
+    collections::{
+        HashMap,
+    },
+};
+
+use futures::{
+    StreamExt,
+};
+
+use blockwheel_fs_ero::{
+    Deleted,
+    ShardedPid,
+    ShardedBlockId,
+    ShardingStrategy,
+    ShardingError,
+    ReadBlockError,
+};
+
+mod common;
+
+use common::{
+    Env,
+    ram_params,
+};
+
+const WHEEL_SIZE_BYTES: usize = 4 * 1024 * 1024;
+
+fn start(env: &mut Env, shards_count: usize, strategy: ShardingStrategy) -> ShardedPid {
+    let shards = (0 .. shards_count)
+        .map(|_| env.start(ram_params(WHEEL_SIZE_BYTES)))
+        .collect();
+    ShardedPid::new(shards, strategy).unwrap()
+}
+
+async fn collect_blocks(sharded: &mut ShardedPid) -> HashMap<ShardedBlockId, Vec<u8>> {
+    let iter_blocks = sharded.iter_blocks().await.unwrap();
+    let blocks_total_count = iter_blocks.blocks_total_count;
+    let mut blocks_stream = iter_blocks.blocks;
+    let mut blocks = HashMap::new();
+    while let Some(item) = blocks_stream.next().await {
+        let (block_id, block_bytes) = item.unwrap();
+        assert!(blocks.insert(block_id, block_bytes.to_vec()).is_none());
+    }
+    assert_eq!(blocks.len(), blocks_total_count);
+    blocks
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn round_robin_spreads_blocks() {
+    let mut env = Env::new();
+    let mut sharded = start(&mut env, 3, ShardingStrategy::RoundRobin);
+    assert_eq!(sharded.shards_count(), 3);
+
+    let mut expected = HashMap::new();
+    let mut shard_counts = [0; 3];
+    for seed in 0 .. 9 {
+        let block_bytes = env.block(seed, 1024);
+        let block_id = sharded.write_block(block_bytes.clone()).await.unwrap();
+        shard_counts[block_id.shard] += 1;
+        expected.insert(block_id, block_bytes.to_vec());
+    }
+    assert_eq!(shard_counts, [3, 3, 3]);
+
+    for (block_id, block_bytes) in &expected {
+        assert_eq!(&sharded.read_block(block_id.clone()).await.unwrap().to_vec(), block_bytes);
+    }
+    assert_eq!(collect_blocks(&mut sharded).await, expected);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn delete_and_unknown_shard() {
+    let mut env = Env::new();
+    let mut sharded = start(&mut env, 2, ShardingStrategy::MostFreeSpace);
+
+    let block_id = sharded.write_block(env.block(0, 1024)).await.unwrap();
+    assert!(matches!(sharded.delete_block(block_id.clone()).await, Ok(Deleted)));
+    assert!(matches!(sharded.read_block(block_id.clone()).await, Err(ReadBlockError::NotFound)));
+
+    let unknown_block_id = ShardedBlockId { shard: 2, ..block_id };
+    assert!(matches!(sharded.read_block(unknown_block_id).await, Err(ReadBlockError::NotFound)));
+    assert!(collect_blocks(&mut sharded).await.is_empty());
+}
+
+#[test]
+fn no_shards_is_an_error() {
+    assert!(matches!(ShardedPid::new(Vec::new(), ShardingStrategy::RoundRobin), Err(ShardingError::NoShards)));
+}